@@ -1,14 +1,31 @@
+use std::cell::RefCell;
 use std::ffi::c_void;
+use std::time::Duration;
 
-use windows::core::{Interface, GUID, HRESULT, PCWSTR, PWSTR};
-use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
+use serde::{Deserialize, Serialize};
+use windows::core::{implement, Interface, GUID, HRESULT, PCWSTR, PWSTR};
+use windows::Win32::Devices::FunctionDiscovery::{
+    PKEY_DeviceClass_IconPath, PKEY_Device_ContainerId, PKEY_Device_FriendlyName,
+};
+use windows::Win32::Foundation::{E_NOINTERFACE, E_NOTIMPL, ERROR_NOT_FOUND, PROPERTYKEY, S_OK};
 use windows::Win32::Media::Audio::{
-    eConsole, eRender, IMMDeviceEnumerator, MMDeviceEnumerator, DEVICE_STATE_ACTIVE,
+    self as win_audio, eCapture, eCommunications, eConsole, eMultimedia, eRender, EDataFlow, ERole, EndpointFormFactor,
+    IAudioSessionControl2, IAudioSessionManager2, IDeviceTopology, ISimpleAudioVolume,
+    IMMDevice, IMMDeviceEnumerator, IMMNotificationClient, IMMNotificationClient_Impl, IPart,
+    MMDeviceEnumerator, PKEY_AudioEndpoint_FormFactor, DEVICE_STATE,
+    DEVICE_STATEMASK_ALL, DEVICE_STATE_ACTIVE, DEVICE_STATE_DISABLED, DEVICE_STATE_NOTPRESENT, DEVICE_STATE_UNPLUGGED,
 };
+use windows::Win32::Media::Audio::Endpoints::IAudioEndpointVolume;
+use windows::Win32::Media::KernelStreaming::{IKsJackDescription, KSJACK_DESCRIPTION};
 use windows::Win32::System::Com::{
-    CoCreateInstance, CoTaskMemFree, CLSCTX_ALL, STGM_READ,
+    CoCreateInstance, CoTaskMemFree, CLSCTX_ALL, CLSCTX_INPROC_SERVER, STGM_READ,
 };
+use windows::Win32::System::Environment::ExpandEnvironmentStringsW;
+use windows::Win32::UI::Shell::ExtractIconExW;
 use windows::Win32::UI::Shell::PropertiesSystem::IPropertyStore;
+use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId, HICON};
+
+use crate::error::{Error, Result};
 
 // Undocumented IPolicyConfig COM interface GUIDs
 const CLSID_POLICY_CONFIG_CLIENT: GUID =
@@ -16,17 +33,165 @@ const CLSID_POLICY_CONFIG_CLIENT: GUID =
 const IID_IPOLICY_CONFIG: GUID =
     GUID::from_u128(0xf8679f50_850a_41cf_9c72_430f290290c8);
 
+// Container ID Windows assigns to every built-in device, so it doesn't identify one endpoint
+const SYSTEM_CONTAINER_ID: &str = "{00000000-0000-0000-ffff-ffffffffffff}";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DeviceState {
+    Active,
+    Disabled,
+    Unplugged,
+    NotPresent,
+}
+
+impl DeviceState {
+    fn from_raw(state: DEVICE_STATE) -> Self {
+        match state {
+            DEVICE_STATE_DISABLED => DeviceState::Disabled,
+            DEVICE_STATE_UNPLUGGED => DeviceState::Unplugged,
+            DEVICE_STATE_NOTPRESENT => DeviceState::NotPresent,
+            _ => DeviceState::Active,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DeviceState::Active => "active",
+            DeviceState::Disabled => "disabled",
+            DeviceState::Unplugged => "unplugged",
+            DeviceState::NotPresent => "not present",
+        }
+    }
+}
+
+/// Endpoint form factor as reported by the driver (PKEY_AudioEndpoint_FormFactor).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FormFactor {
+    Speakers,
+    Headphones,
+    Headset,
+    LineLevel,
+    Digital,
+    Other,
+}
+
+impl FormFactor {
+    fn from_raw(form_factor: EndpointFormFactor) -> Self {
+        match form_factor {
+            win_audio::Speakers => FormFactor::Speakers,
+            win_audio::Headphones => FormFactor::Headphones,
+            win_audio::Headset => FormFactor::Headset,
+            win_audio::LineLevel => FormFactor::LineLevel,
+            win_audio::SPDIF | win_audio::DigitalAudioDisplayDevice => FormFactor::Digital,
+            _ => FormFactor::Other,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FormFactor::Speakers => "speakers",
+            FormFactor::Headphones => "headphones",
+            FormFactor::Headset => "headset",
+            FormFactor::LineLevel => "line out",
+            FormFactor::Digital => "digital",
+            FormFactor::Other => "unknown",
+        }
+    }
+}
+
+/// Whether an endpoint plays audio (output) or records it (microphone).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Render,
+    Capture,
+}
+
+impl Direction {
+    fn to_raw(self) -> EDataFlow {
+        match self {
+            Direction::Render => eRender,
+            Direction::Capture => eCapture,
+        }
+    }
+}
+
+/// Default-device role. Windows keeps a separate default for each; switching sets all three
+/// unless the config's `roles` narrows it down.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Console,
+    Multimedia,
+    Communications,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Console, Role::Multimedia, Role::Communications];
+
+    /// Parse a role name as used on the command line (`console`, `multimedia`, `communications`).
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "console" => Some(Role::Console),
+            "multimedia" => Some(Role::Multimedia),
+            "communications" => Some(Role::Communications),
+            _ => None,
+        }
+    }
+
+    fn from_raw(role: ERole) -> Option<Self> {
+        Role::ALL.into_iter().find(|r| r.to_raw() == role)
+    }
+
+    fn to_raw(self) -> ERole {
+        match self {
+            Role::Console => eConsole,
+            Role::Multimedia => eMultimedia,
+            Role::Communications => eCommunications,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct AudioDevice {
     pub id: String,
     pub name: String,
+    pub state: DeviceState,
+    /// Whether the physical jack is plugged in. Endpoints without jack detection
+    /// (USB, Bluetooth, HDMI on most drivers) always report connected unless unplugged.
+    pub connected: bool,
+    pub form_factor: FormFactor,
+    /// PnP container ID shared by all endpoints of one physical device. Unlike the endpoint ID
+    /// it survives moving a USB device to another port. None for built-in devices, which all
+    /// share the system container.
+    pub container_id: Option<String>,
+}
+
+/// List all active devices in `direction`.
+pub fn list_devices(direction: Direction) -> Result<Vec<AudioDevice>> {
+    enumerate_devices(direction, DEVICE_STATE_ACTIVE)
+}
+
+/// List active, disabled, and unplugged audio output devices. Disabled ones can be re-enabled;
+/// unplugged ones can be picked ahead of time but not switched to until they're plugged in.
+pub fn list_devices_with_disabled() -> Result<Vec<AudioDevice>> {
+    let states = DEVICE_STATE_ACTIVE.0 | DEVICE_STATE_DISABLED.0 | DEVICE_STATE_UNPLUGGED.0;
+    enumerate_devices(Direction::Render, DEVICE_STATE(states))
+}
+
+/// List every known device in `direction`, including unplugged and no-longer-present ones.
+pub fn list_all_devices(direction: Direction) -> Result<Vec<AudioDevice>> {
+    enumerate_devices(direction, DEVICE_STATE(DEVICE_STATEMASK_ALL))
+}
+
+fn enumerate_devices(direction: Direction, state_mask: DEVICE_STATE) -> Result<Vec<AudioDevice>> {
+    enumerate_devices_raw(direction, state_mask).map_err(Error::Enumerate)
 }
 
-/// List all active audio output (render) devices.
-pub fn list_devices() -> windows::core::Result<Vec<AudioDevice>> {
+fn enumerate_devices_raw(direction: Direction, state_mask: DEVICE_STATE) -> windows::core::Result<Vec<AudioDevice>> {
     unsafe {
         let enumerator: IMMDeviceEnumerator =
             CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
-        let collection = enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
+        let collection = enumerator.EnumAudioEndpoints(direction.to_raw(), state_mask)?;
         let count = collection.GetCount()?;
 
         let mut devices = Vec::new();
@@ -34,45 +199,476 @@ pub fn list_devices() -> windows::core::Result<Vec<AudioDevice>> {
             let device = collection.Item(i)?;
 
             // Get device ID
-            let id_pwstr: PWSTR = device.GetId()?;
-            let id = id_pwstr.to_string()?;
-            CoTaskMemFree(Some(id_pwstr.0 as *const c_void));
+            let id = take_pwstr(device.GetId()?);
 
             // Get friendly name from property store
             let store: IPropertyStore = device.OpenPropertyStore(STGM_READ)?;
             let prop = store.GetValue(&PKEY_Device_FriendlyName)?;
             let name = prop.to_string();
+            let form_factor = store
+                .GetValue(&PKEY_AudioEndpoint_FormFactor)
+                .ok()
+                .and_then(|v| u32::try_from(&v).ok())
+                .map_or(FormFactor::Other, |v| FormFactor::from_raw(EndpointFormFactor(v as i32)));
 
-            devices.push(AudioDevice { id, name });
+            let state = DeviceState::from_raw(device.GetState()?);
+            let connected = state != DeviceState::Unplugged && jack_connected(&device).unwrap_or(true);
+
+            let container_id = store
+                .GetValue(&PKEY_Device_ContainerId)
+                .ok()
+                .map(|v| v.to_string().to_ascii_lowercase())
+                .filter(|c| !c.is_empty() && c != SYSTEM_CONTAINER_ID);
+
+            devices.push(AudioDevice { id, name, state, connected, form_factor, container_id });
         }
 
         Ok(devices)
     }
 }
 
-/// Get the endpoint ID of the current default audio output device.
-pub fn get_default_device_id() -> windows::core::Result<String> {
+/// Read jack presence via the device topology: endpoint connector -> adapter part ->
+/// IKsJackDescription. Returns None if the driver doesn't expose jack information.
+fn jack_connected(device: &IMMDevice) -> Option<bool> {
+    unsafe {
+        let topology: IDeviceTopology = device.Activate(CLSCTX_ALL, None).ok()?;
+        let connector = topology.GetConnector(0).ok()?;
+        let part: IPart = connector.GetConnectedTo().ok()?.cast().ok()?;
+
+        let mut raw = std::ptr::null_mut();
+        part.Activate(CLSCTX_INPROC_SERVER.0, &IKsJackDescription::IID, Some(&mut raw))
+            .ok()?;
+        let jacks = IKsJackDescription::from_raw(raw);
+
+        let count = jacks.GetJackCount().ok()?;
+        if count == 0 {
+            return None;
+        }
+        let mut any_connected = false;
+        for i in 0..count {
+            let mut desc = KSJACK_DESCRIPTION::default();
+            jacks.GetJackDescription(i, &mut desc).ok()?;
+            any_connected |= desc.IsConnected.as_bool();
+        }
+        Some(any_connected)
+    }
+}
+
+/// The device calls the switching logic makes, so it can run against something other than the
+/// real endpoints.
+pub trait AudioBackend {
+    /// Active devices in `direction`.
+    fn list_devices(&self, direction: Direction) -> Result<Vec<AudioDevice>>;
+    /// Endpoint ID of the default device in `direction` for `role`.
+    fn get_default_device_id(&self, direction: Direction, role: Role) -> Result<String>;
+    /// Make `device_id` the default for just `roles`.
+    fn set_default_device(&self, device_id: &str, roles: &[Role]) -> Result<()>;
+    /// Like `set_default_device`, with `from_id` and `to_id` muted across the switch.
+    fn set_default_device_muted(&self, from_id: &str, to_id: &str, roles: &[Role]) -> Result<()>;
+}
+
+/// The Windows audio endpoints, through MMDevice and IPolicyConfig.
+pub struct WindowsAudio;
+
+impl AudioBackend for WindowsAudio {
+    fn list_devices(&self, direction: Direction) -> Result<Vec<AudioDevice>> {
+        list_devices(direction)
+    }
+
+    fn get_default_device_id(&self, direction: Direction, role: Role) -> Result<String> {
+        get_default_device_id_for_role(direction, role)
+    }
+
+    fn set_default_device(&self, device_id: &str, roles: &[Role]) -> Result<()> {
+        set_default_device_for_roles(device_id, roles)
+    }
+
+    fn set_default_device_muted(&self, from_id: &str, to_id: &str, roles: &[Role]) -> Result<()> {
+        set_default_device_muted(from_id, to_id, roles)
+    }
+}
+
+/// Get the endpoint ID of the current default device in `direction`.
+pub fn get_default_device_id(direction: Direction) -> Result<String> {
+    get_default_device_id_for_role(direction, Role::Console)
+}
+
+/// Get the endpoint ID of the default device in `direction` for `role`.
+pub fn get_default_device_id_for_role(direction: Direction, role: Role) -> Result<String> {
+    unsafe {
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let device = match enumerator.GetDefaultAudioEndpoint(direction.to_raw(), role.to_raw()) {
+            Ok(device) => device,
+            Err(e) if e.code() == ERROR_NOT_FOUND.to_hresult() => return Err(Error::NoDefaultDevice),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(take_pwstr(device.GetId()?))
+    }
+}
+
+/// ID of the first active output device whose friendly name is exactly `name`.
+pub fn find_device_by_name(name: &str) -> Option<String> {
+    list_devices(Direction::Render).ok()?.into_iter().find(|d| d.name == name).map(|d| d.id)
+}
+
+/// Friendly name of the endpoint with `device_id`, e.g. "Speakers (Realtek(R) Audio)".
+pub fn get_device_name(device_id: &str) -> Option<String> {
+    unsafe {
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL).ok()?;
+        let wide = wide_str(device_id);
+        let device = enumerator.GetDevice(PCWSTR(wide.as_ptr())).ok()?;
+        let store: IPropertyStore = device.OpenPropertyStore(STGM_READ).ok()?;
+        let name = store.GetValue(&PKEY_Device_FriendlyName).ok()?.to_string();
+        (!name.is_empty()).then_some(name)
+    }
+}
+
+/// Load the small icon Windows shows for this device in the Sound panel.
+///
+/// Reads PKEY_DeviceClass_IconPath (e.g. `%windir%\system32\mmres.dll,-3011`) and extracts
+/// it with ExtractIconExW. Returns None if the device has no icon path or it can't be loaded.
+/// The caller owns the returned icon.
+pub fn get_device_icon(device_id: &str) -> Option<HICON> {
+    unsafe {
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL).ok()?;
+        let wide_id = wide_str(device_id);
+        let device = enumerator.GetDevice(PCWSTR(wide_id.as_ptr())).ok()?;
+        let store: IPropertyStore = device.OpenPropertyStore(STGM_READ).ok()?;
+        let icon_path = store.GetValue(&PKEY_DeviceClass_IconPath).ok()?.to_string();
+
+        // "file,index" where a negative index is a resource ID
+        let (file, index) = match icon_path.rsplit_once(',') {
+            Some((file, index)) => (file, index.trim().parse().unwrap_or(0)),
+            None => (icon_path.as_str(), 0),
+        };
+        if file.is_empty() {
+            return None;
+        }
+
+        let file_wide = wide_str(file);
+        let mut expanded = [0u16; 260];
+        let len = ExpandEnvironmentStringsW(PCWSTR(file_wide.as_ptr()), Some(&mut expanded));
+        if len == 0 || len as usize > expanded.len() {
+            return None;
+        }
+
+        let mut small = HICON::default();
+        let extracted =
+            ExtractIconExW(PCWSTR(expanded.as_ptr()), index, None, Some(&mut small), 1);
+        (extracted > 0 && !small.is_invalid()).then_some(small)
+    }
+}
+
+/// Endpoint change reported by the notification client registered with `watch_devices`.
+pub enum DeviceEvent {
+    /// The default render device for a role changed.
+    DefaultChanged(Role, String),
+    /// An endpoint was added or became active (e.g. a headset was plugged in).
+    Arrived(String),
+}
+
+type DeviceEventHandler = Box<dyn Fn(DeviceEvent) + Send + Sync>;
+
+thread_local! {
+    // Enumerator + client from watch_devices, kept so the callback can be unregistered
+    static DEVICE_WATCH: RefCell<Option<(IMMDeviceEnumerator, IMMNotificationClient)>> =
+        const { RefCell::new(None) };
+    // Sessions lowered by duck_background_sessions, with the volume to restore
+    static DUCKED: RefCell<Vec<(ISimpleAudioVolume, f32)>> = const { RefCell::new(Vec::new()) };
+}
+
+#[implement(IMMNotificationClient)]
+struct NotificationClient {
+    handler: DeviceEventHandler,
+}
+
+// Callbacks arrive on an MMDevice worker thread. Handlers must not call back into the
+// audio APIs synchronously — post to the UI thread instead.
+impl IMMNotificationClient_Impl for NotificationClient_Impl {
+    fn OnDeviceStateChanged(&self, id: &PCWSTR, state: DEVICE_STATE) -> windows::core::Result<()> {
+        if state == DEVICE_STATE_ACTIVE && !id.is_null() {
+            let id = unsafe { String::from_utf16_lossy(id.as_wide()) };
+            (self.handler)(DeviceEvent::Arrived(id));
+        }
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, id: &PCWSTR) -> windows::core::Result<()> {
+        if !id.is_null() {
+            let id = unsafe { String::from_utf16_lossy(id.as_wide()) };
+            (self.handler)(DeviceEvent::Arrived(id));
+        }
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, _id: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDefaultDeviceChanged(
+        &self,
+        flow: EDataFlow,
+        role: ERole,
+        default_id: &PCWSTR,
+    ) -> windows::core::Result<()> {
+        // set_default_device fires this once per role
+        if flow == eRender
+            && let Some(role) = Role::from_raw(role)
+            && !default_id.is_null()
+        {
+            let id = unsafe { String::from_utf16_lossy(default_id.as_wide()) };
+            (self.handler)(DeviceEvent::DefaultChanged(role, id));
+        }
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(&self, _id: &PCWSTR, _key: &PROPERTYKEY) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
+
+/// Register an endpoint notification client that forwards changes to `handler`.
+/// Call `unwatch_devices` from the same thread before exiting.
+pub fn watch_devices(handler: impl Fn(DeviceEvent) + Send + Sync + 'static) -> Result<()> {
     unsafe {
         let enumerator: IMMDeviceEnumerator =
             CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
-        let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
-        let id_pwstr: PWSTR = device.GetId()?;
-        let id = id_pwstr.to_string()?;
-        CoTaskMemFree(Some(id_pwstr.0 as *const c_void));
-        Ok(id)
+        let client: IMMNotificationClient = NotificationClient {
+            handler: Box::new(handler),
+        }
+        .into();
+        enumerator.RegisterEndpointNotificationCallback(&client)?;
+        DEVICE_WATCH.with(|watch| *watch.borrow_mut() = Some((enumerator, client)));
+    }
+    Ok(())
+}
+
+/// Unregister the notification client installed by `watch_devices`, if any.
+pub fn unwatch_devices() {
+    if let Some((enumerator, client)) = DEVICE_WATCH.with(|watch| watch.borrow_mut().take()) {
+        unsafe {
+            let _ = enumerator.UnregisterEndpointNotificationCallback(&client);
+        }
     }
 }
 
 /// Set the default audio output device for all roles (console, multimedia, communications).
+pub fn set_default_device(device_id: &str) -> Result<()> {
+    set_default_device_for_roles(device_id, &Role::ALL)
+}
+
+/// Set the default audio device for just the given roles, leaving the others alone.
 ///
 /// Uses the undocumented IPolicyConfig COM interface via raw vtable access.
-pub fn set_default_device(device_id: &str) -> windows::core::Result<()> {
+pub fn set_default_device_for_roles(device_id: &str, roles: &[Role]) -> Result<()> {
+    if device_state(device_id)? != DeviceState::Active {
+        return Err(Error::DeviceUnavailable(device_id.to_string()));
+    }
+
+    // SetDefaultEndpoint is at vtable index 13:
+    //   IUnknown (3 methods) + 10 IPolicyConfig methods before SetDefaultEndpoint
+    type SetDefaultEndpointFn = unsafe extern "system" fn(*mut c_void, PCWSTR, u32) -> HRESULT;
+
+    with_policy_config(|policy_config, pc_vtable| unsafe {
+        let wide = wide_str(device_id);
+        let set_default_endpoint: SetDefaultEndpointFn = std::mem::transmute(*pc_vtable.add(13));
+
+        for role in roles {
+            set_default_endpoint(policy_config, PCWSTR(wide.as_ptr()), role.to_raw().0 as u32).ok()?;
+        }
+        Ok(())
+    })
+}
+
+// How long both endpoints stay muted after the switch, while the audio engine reroutes
+const MUTE_SETTLE: Duration = Duration::from_millis(150);
+
+/// Switch the default device from `from_id` to `to_id` with both endpoints muted, hiding the
+/// pop some drivers make. Mute states are restored afterwards, even if the switch fails.
+pub fn set_default_device_muted(from_id: &str, to_id: &str, roles: &[Role]) -> Result<()> {
+    let guard = MuteGuard::mute(&[from_id, to_id]);
+    let result = set_default_device_for_roles(to_id, roles);
+    if result.is_ok() && !guard.muted.is_empty() {
+        std::thread::sleep(MUTE_SETTLE);
+    }
+    result
+}
+
+/// Mutes endpoints on creation and unmutes the ones it muted when dropped. Endpoints that were
+/// already muted (or whose volume control couldn't be opened) are left alone.
+struct MuteGuard {
+    muted: Vec<IAudioEndpointVolume>,
+}
+
+impl MuteGuard {
+    fn mute(device_ids: &[&str]) -> Self {
+        let mut muted = Vec::new();
+        for id in device_ids {
+            let Ok(volume) = endpoint_volume(id) else {
+                continue;
+            };
+            unsafe {
+                if volume.GetMute().is_ok_and(|m| !m.as_bool())
+                    && volume.SetMute(true, std::ptr::null()).is_ok()
+                {
+                    muted.push(volume);
+                }
+            }
+        }
+        MuteGuard { muted }
+    }
+}
+
+impl Drop for MuteGuard {
+    fn drop(&mut self) {
+        for volume in &self.muted {
+            unsafe {
+                let _ = volume.SetMute(false, std::ptr::null());
+            }
+        }
+    }
+}
+
+/// Volume control for an endpoint. Some virtual devices don't offer one; that is reported as
+/// `NoVolumeControl`.
+fn endpoint_volume(device_id: &str) -> Result<IAudioEndpointVolume> {
+    unsafe {
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let wide = wide_str(device_id);
+        let device = enumerator.GetDevice(PCWSTR(wide.as_ptr()))?;
+        device.Activate(CLSCTX_ALL, None).map_err(|e| {
+            if e.code() == E_NOINTERFACE || e.code() == E_NOTIMPL {
+                Error::NoVolumeControl(device_id.to_string())
+            } else {
+                e.into()
+            }
+        })
+    }
+}
+
+/// Set an endpoint's master volume, `scalar` being 0.0-1.0 (clamped).
+pub fn set_volume(device_id: &str, scalar: f32) -> Result<()> {
+    let volume = endpoint_volume(device_id)?;
+    unsafe { Ok(volume.SetMasterVolumeLevelScalar(scalar.clamp(0.0, 1.0), std::ptr::null())?) }
+}
+
+/// Whether the default output device is muted.
+pub fn is_muted() -> Result<bool> {
+    let volume = endpoint_volume(&get_default_device_id(Direction::Render)?)?;
+    unsafe { Ok(volume.GetMute()?.as_bool()) }
+}
+
+/// Mute or unmute the default output device.
+pub fn set_mute(muted: bool) -> Result<()> {
+    let volume = endpoint_volume(&get_default_device_id(Direction::Render)?)?;
+    unsafe { Ok(volume.SetMute(muted, std::ptr::null())?) }
+}
+
+/// Flip the default output device's mute state. Returns the new state (true = muted).
+pub fn toggle_mute() -> Result<bool> {
+    let muted = !is_muted()?;
+    set_mute(muted)?;
+    Ok(muted)
+}
+
+/// Scale the volume of every app session on `device_id` to `level` (0.0-1.0) of its current
+/// value, except the foreground app and system sounds. Call `restore_ducked_sessions` to undo.
+pub fn duck_background_sessions(device_id: &str, level: f32) -> Result<()> {
+    restore_ducked_sessions();
+
+    unsafe {
+        let mut foreground_pid = 0u32;
+        GetWindowThreadProcessId(GetForegroundWindow(), Some(&mut foreground_pid));
+
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let wide = wide_str(device_id);
+        let device = enumerator.GetDevice(PCWSTR(wide.as_ptr()))?;
+        let manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+        let sessions = manager.GetSessionEnumerator()?;
+
+        let mut ducked = Vec::new();
+        for i in 0..sessions.GetCount()? {
+            let Ok(control) = sessions.GetSession(i) else {
+                continue;
+            };
+            let Ok(control2) = control.cast::<IAudioSessionControl2>() else {
+                continue;
+            };
+            // IsSystemSoundsSession returns S_OK for system sounds, S_FALSE otherwise
+            if control2.IsSystemSoundsSession() == S_OK
+                || control2.GetProcessId().is_ok_and(|pid| pid == foreground_pid)
+            {
+                continue;
+            }
+            let Ok(volume) = control.cast::<ISimpleAudioVolume>() else {
+                continue;
+            };
+            if let Ok(original) = volume.GetMasterVolume()
+                && volume.SetMasterVolume(original * level, std::ptr::null()).is_ok()
+            {
+                ducked.push((volume, original));
+            }
+        }
+        DUCKED.with(|d| *d.borrow_mut() = ducked);
+    }
+    Ok(())
+}
+
+/// Put sessions lowered by `duck_background_sessions` back to their original volume.
+/// Sessions that have ended since are skipped.
+pub fn restore_ducked_sessions() {
+    for (volume, original) in DUCKED.with(|d| d.take()) {
+        unsafe {
+            let _ = volume.SetMasterVolume(original, std::ptr::null());
+        }
+    }
+}
+
+/// Enable a disabled audio endpoint (same as "Enable" in the Sound control panel).
+///
+/// Usually requires the process to be elevated; fails with E_ACCESSDENIED otherwise.
+pub fn enable_device(device_id: &str) -> Result<()> {
+    device_state(device_id)?;
+
+    // SetEndpointVisibility directly follows SetDefaultEndpoint, at vtable index 14
+    type SetEndpointVisibilityFn = unsafe extern "system" fn(*mut c_void, PCWSTR, i32) -> HRESULT;
+
+    with_policy_config(|policy_config, pc_vtable| unsafe {
+        let wide = wide_str(device_id);
+        let set_endpoint_visibility: SetEndpointVisibilityFn =
+            std::mem::transmute(*pc_vtable.add(14));
+        set_endpoint_visibility(policy_config, PCWSTR(wide.as_ptr()), 1).ok()
+    })
+}
+
+/// Look up an endpoint's state, so a stale ID in the config is reported as `DeviceNotFound`
+/// (and an unplugged one as `DeviceUnavailable`) rather than an opaque IPolicyConfig failure.
+fn device_state(device_id: &str) -> Result<DeviceState> {
     unsafe {
-        // Encode device_id as null-terminated UTF-16
-        let wide: Vec<u16> = device_id.encode_utf16().chain(std::iter::once(0)).collect();
-        let pcwstr = PCWSTR(wide.as_ptr());
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let wide = wide_str(device_id);
+        match enumerator.GetDevice(PCWSTR(wide.as_ptr())) {
+            Ok(device) => Ok(DeviceState::from_raw(device.GetState()?)),
+            Err(e) if e.code() == ERROR_NOT_FOUND.to_hresult() => {
+                Err(Error::DeviceNotFound(device_id.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
 
-        // Create CPolicyConfigClient and QueryInterface for IPolicyConfig
+/// Create CPolicyConfigClient, QueryInterface for IPolicyConfig, and hand the raw pointer and
+/// vtable to `f`. The interface is released afterwards whether or not `f` succeeds.
+fn with_policy_config<T>(
+    f: impl FnOnce(*mut c_void, *const *const c_void) -> windows::core::Result<T>,
+) -> Result<T> {
+    unsafe {
         let unknown: windows::core::IUnknown =
             CoCreateInstance(&CLSID_POLICY_CONFIG_CLIENT, None, CLSCTX_ALL)?;
         let raw = unknown.as_raw();
@@ -88,24 +684,27 @@ pub fn set_default_device(device_id: &str) -> windows::core::Result<()> {
 
         // Access IPolicyConfig vtable
         let pc_vtable = *(policy_config as *const *const *const c_void);
-
-        // SetDefaultEndpoint is at vtable index 13:
-        //   IUnknown (3 methods) + 10 IPolicyConfig methods before SetDefaultEndpoint
-        type SetDefaultEndpointFn =
-            unsafe extern "system" fn(*mut c_void, PCWSTR, u32) -> HRESULT;
-        let set_default_endpoint: SetDefaultEndpointFn =
-            std::mem::transmute(*pc_vtable.add(13));
-
-        // Set for all 3 roles: eConsole=0, eMultimedia=1, eCommunications=2
-        for role in 0..3u32 {
-            set_default_endpoint(policy_config, pcwstr, role).ok()?;
-        }
+        let result = f(policy_config, pc_vtable);
 
         // Release IPolicyConfig
         type ReleaseFn = unsafe extern "system" fn(*mut c_void) -> u32;
         let release: ReleaseFn = std::mem::transmute(*pc_vtable.add(2));
         release(policy_config);
 
-        Ok(())
+        result.map_err(Error::PolicyConfig)
     }
 }
+
+/// Copy a COM-allocated wide string into a `String` and free the original.
+unsafe fn take_pwstr(pwstr: PWSTR) -> String {
+    unsafe {
+        let s = String::from_utf16_lossy(pwstr.as_wide());
+        CoTaskMemFree(Some(pwstr.0 as *const c_void));
+        s
+    }
+}
+
+/// Encode as null-terminated UTF-16.
+fn wide_str(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
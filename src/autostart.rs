@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use windows::core::{w, Interface, BSTR, PCWSTR};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoUninitialize, IPersistFile, CLSCTX_INPROC_SERVER,
+    COINIT_APARTMENTTHREADED,
+};
+use windows::Win32::UI::Shell::{IShellLinkW, ShellLink};
+
+use crate::config;
+use crate::error::Result;
+
+// Shortcut file name in the user's Startup folder
+const SHORTCUT_NAME: &str = "AudioSwitcher.lnk";
+
+fn startup_shortcut_path() -> Option<PathBuf> {
+    // %APPDATA%\Microsoft\Windows\Start Menu\Programs\Startup\AudioSwitcher.lnk
+    Some(
+        dirs::config_dir()?
+            .join("Microsoft")
+            .join("Windows")
+            .join("Start Menu")
+            .join("Programs")
+            .join("Startup")
+            .join(SHORTCUT_NAME),
+    )
+}
+
+/// Whether the app is set to start at login.
+pub fn is_enabled() -> bool {
+    startup_shortcut_path().is_some_and(|p| p.exists())
+}
+
+/// Create or remove the Startup-folder shortcut to the current exe.
+///
+/// The shortcut stores the exe path and arguments separately, so paths with spaces need no
+/// quoting. It passes `--config` or `--portable` along so login starts use the same config.
+pub fn set_enabled(enable: bool) -> Result<()> {
+    let Some(shortcut_path) = startup_shortcut_path() else {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "APPDATA not found").into());
+    };
+    if !enable {
+        return match std::fs::remove_file(&shortcut_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+
+    let exe_path = std::env::current_exe()?;
+    let exe_dir = exe_path.parent().unwrap_or(&exe_path);
+
+    unsafe {
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+
+        let result: windows::core::Result<()> = (|| {
+            let shell_link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+
+            let exe_wide = wide_str(&exe_path.to_string_lossy());
+            shell_link.SetPath(PCWSTR(exe_wide.as_ptr()))?;
+
+            let dir_wide = wide_str(&exe_dir.to_string_lossy());
+            shell_link.SetWorkingDirectory(PCWSTR(dir_wide.as_ptr()))?;
+
+            // Keep using the same config when started at login
+            if let Some(path) = config::config_override() {
+                let args = wide_str(&format!("--config \"{}\"", path.display()));
+                shell_link.SetArguments(PCWSTR(args.as_ptr()))?;
+            } else if config::is_portable() {
+                shell_link.SetArguments(w!("--portable"))?;
+            }
+
+            let persist_file: IPersistFile = shell_link.cast()?;
+            let link_wide = BSTR::from(shortcut_path.to_string_lossy().as_ref());
+            persist_file.Save(&link_wide, true)?;
+
+            Ok(())
+        })();
+
+        CoUninitialize();
+        Ok(result?)
+    }
+}
+
+fn wide_str(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
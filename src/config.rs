@@ -1,43 +1,855 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
 
-#[derive(Serialize, Deserialize)]
+use crate::audio::Role;
+use crate::error::{Error, Result};
+use crate::hotkey;
+
+/// What the toggle hotkey (and tray left-click) switches between.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToggleMode {
+    /// Flip between the configured speakers and headphones.
+    #[default]
+    Slots,
+    /// Swap between the current and the previous default device, whatever they were.
+    Mru,
+}
+
+/// What a "switch to X" command does when X is already the default device.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatAction {
+    /// Do nothing.
+    #[default]
+    Nothing,
+    /// Replay the switch sound as confirmation.
+    Resound,
+    /// Move on to the next configured device.
+    Cycle,
+}
+
+/// How much goes into switcher.log.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// No log file.
+    Off,
+    /// Failures only.
+    Error,
+    /// Switches and failures.
+    #[default]
+    Info,
+    /// Also hotkey registration, device lists, and default-device change events.
+    Debug,
+}
+
+/// How lines in switcher.log are written.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Timestamped plain text, for reading.
+    #[default]
+    Text,
+    /// One JSON object per line, for tools that tail the log.
+    Json,
+}
+
+/// Where a device sits in the ring, which decides its tray icon and tooltip.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// First device in the ring.
+    Speakers,
+    /// Second device in the ring.
+    Headphones,
+    /// Any later device, or one that isn't configured.
+    Other,
+}
+
+/// Action bound to a tray icon mouse gesture.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickAction {
+    #[default]
+    None,
+    Toggle,
+    Reconfigure,
+    SoundSettings,
+}
+
+/// A named speakers/headphones pair that can be swapped in for the top-level one.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub speakers: String,
+    pub headphones: String,
+    /// Optional hotkey that activates this profile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hotkey: Option<String>,
+}
+
+/// Device to switch to while a given app is in the foreground.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AppRule {
+    /// Executable name, e.g. "chrome.exe" (the ".exe" may be left off; case doesn't matter).
+    pub app: String,
+    pub device: String,
+}
+
+/// A hotkey that switches straight to one device.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DeviceHotkey {
+    pub device: String,
+    pub hotkey: String,
+}
+
+/// Schema version written to new and migrated configs; see `migrate`.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Schema version the file was written with (0 = from before versioning).
+    #[serde(default)]
+    pub version: u32,
     #[serde(alias = "device_a")]
     pub speakers: String,
     #[serde(alias = "device_b")]
     pub headphones: String,
+    /// Friendly names recorded at setup, used to find the devices again if their IDs change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speakers_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headphones_name: Option<String>,
+    /// Devices the toggle hotkey cycles through, in order. When empty, the ring is
+    /// `speakers` then `headphones`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<String>,
     pub hotkey: String,
+    /// Hotkeys that switch directly to a given device, alongside the toggle `hotkey`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hotkeys: Vec<DeviceHotkey>,
+    /// Optional hotkey that cycles backwards through the configured devices.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_hotkey: Option<String>,
+    /// Microphones (capture device IDs) that `mic-toggle` and `mic_hotkey` cycle through.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mics: Vec<String>,
+    /// Optional hotkey that switches to the next microphone in `mics`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mic_hotkey: Option<String>,
+    /// Optional hotkey that opens the Windows Sound settings page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sound_settings_hotkey: Option<String>,
+    /// Optional hotkey that mutes/unmutes the current output device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mute_hotkey: Option<String>,
+    /// Optional hotkey that shows the current device on screen while it's held.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peek_hotkey: Option<String>,
     #[serde(default = "default_true")]
     pub notify_sound: bool,
+    /// WAV file to play on switch instead of notify.wav or the built-in sound.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify_sound_path: Option<String>,
+    /// WAV file to play when switching to the speakers, overriding `notify_sound_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speakers_sound_path: Option<String>,
+    /// WAV file to play when switching to the headphones, overriding `notify_sound_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headphones_sound_path: Option<String>,
+    /// Show a toast with an Undo button after each switch.
+    #[serde(default)]
+    pub undo_toast: bool,
+    /// Show a "Switched to ..." balloon from the tray icon after each switch.
+    #[serde(default = "default_true")]
+    pub show_notifications: bool,
+    /// Switch to a configured device as soon as it's plugged in or enabled.
+    #[serde(default)]
+    pub auto_switch_on_connect: bool,
+    /// Switch devices automatically when the laptop moves between AC and battery power.
+    #[serde(default)]
+    pub power_switch: bool,
+    /// Device to use on AC power (defaults to speakers).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ac_device: Option<String>,
+    /// Device to use on battery power (defaults to headphones).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub battery_device: Option<String>,
+    /// Device to make default every time the app starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_device: Option<String>,
+    /// Switch back to `last_device` when the app starts (ignored if `startup_device` is set).
+    #[serde(default)]
+    pub restore_on_start: bool,
+    /// Device most recently switched to; kept up to date while `restore_on_start` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_device: Option<String>,
+    #[serde(default)]
+    pub toggle_mode: ToggleMode,
+    #[serde(default)]
+    pub repeat_action: RepeatAction,
+    /// Switch devices by `app_rules` as the foreground app changes.
+    #[serde(default)]
+    pub app_switch: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_rules: Vec<AppRule>,
+    /// Accept get/toggle/set commands on the `\\.\pipe\AudioSwitcher` named pipe.
+    #[serde(default)]
+    pub enable_pipe: bool,
+    /// Run without a tray icon (hotkeys and CLI only), like `--no-tray`.
+    #[serde(default)]
+    pub headless: bool,
+    /// Keep cycling through the devices while the toggle (or previous) hotkey is held.
+    #[serde(default)]
+    pub repeat_hotkey: bool,
+    /// Toggles from the hotkeys or the tray within this many milliseconds of the last one are
+    /// ignored (0 = no debounce).
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    /// What a single click on the tray icon does.
+    #[serde(default = "default_click_action")]
+    pub click_action: ClickAction,
+    /// What double-clicking the tray icon does.
+    #[serde(default)]
+    pub double_click_action: ClickAction,
+    /// Show the active device's own Windows icon in the tray.
+    #[serde(default)]
+    pub use_system_device_icons: bool,
+    /// .ico or .png file shown in the tray for the speakers instead of the built-in icon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speakers_icon: Option<String>,
+    /// .ico or .png file shown in the tray for the headphones instead of the built-in icon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headphones_icon: Option<String>,
+    /// Mute both endpoints while the default is switched, to hide the driver pop.
+    #[serde(default)]
+    pub mute_during_switch: bool,
+    /// Lower background apps' volume while the headphones are the default.
+    #[serde(default)]
+    pub duck_on_headphones: bool,
+    /// Percentage of their volume that ducked apps keep.
+    #[serde(default = "default_duck_level")]
+    pub duck_level: u8,
+    /// How long setup waits for a second output device to appear before giving up.
+    #[serde(default = "default_device_wait_secs")]
+    pub device_wait_secs: u32,
+    /// Extra attempts when setting the default device fails (0 = fail immediately).
+    #[serde(default)]
+    pub switch_retries: u32,
+    /// Delay before the first retry; doubles for each further attempt.
+    #[serde(default = "default_switch_retry_delay_ms")]
+    pub switch_retry_delay_ms: u64,
+    /// Check that the new default actually took after switching, and redo it if not.
+    #[serde(default)]
+    pub verify_switch: bool,
+    /// Which role's default the tray icon, tooltip, and menu check mark show.
+    #[serde(default = "default_state_role")]
+    pub state_role: Role,
+    /// Show the communications device in the tray icon corner when it differs from the media one.
+    #[serde(default)]
+    pub tray_shows_split: bool,
+    /// Mark the tray icon and tooltip while the output is muted (polls the mute state).
+    #[serde(default = "default_true")]
+    pub show_mute_state: bool,
+    /// What gets written to switcher.log next to the config.
+    #[serde(default)]
+    pub log_level: LogLevel,
+    /// Whether switcher.log is plain text or JSON lines.
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// Device ID -> volume percentage (0-100) to set when switching to it.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub volumes: BTreeMap<String, u8>,
+    /// Device ID -> the default roles switching to it sets (all three if it isn't listed).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roles: BTreeMap<String, Vec<Role>>,
+    /// Container ID -> last endpoint ID seen for it, used to find a device again after it moves
+    /// to another USB port. Maintained automatically.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub device_containers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Name of the profile whose pair is currently in `speakers`/`headphones`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_profile: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            speakers: String::new(),
+            headphones: String::new(),
+            speakers_name: None,
+            headphones_name: None,
+            devices: Vec::new(),
+            hotkey: "Ctrl+Alt+S".to_string(),
+            hotkeys: Vec::new(),
+            prev_hotkey: None,
+            mics: Vec::new(),
+            mic_hotkey: None,
+            sound_settings_hotkey: None,
+            mute_hotkey: None,
+            peek_hotkey: None,
+            notify_sound: true,
+            notify_sound_path: None,
+            speakers_sound_path: None,
+            headphones_sound_path: None,
+            undo_toast: false,
+            show_notifications: true,
+            auto_switch_on_connect: false,
+            power_switch: false,
+            ac_device: None,
+            battery_device: None,
+            startup_device: None,
+            restore_on_start: false,
+            last_device: None,
+            toggle_mode: ToggleMode::Slots,
+            repeat_action: RepeatAction::Nothing,
+            app_switch: false,
+            app_rules: Vec::new(),
+            enable_pipe: false,
+            headless: false,
+            repeat_hotkey: false,
+            debounce_ms: default_debounce_ms(),
+            click_action: ClickAction::Toggle,
+            double_click_action: ClickAction::None,
+            use_system_device_icons: false,
+            speakers_icon: None,
+            headphones_icon: None,
+            mute_during_switch: false,
+            duck_on_headphones: false,
+            duck_level: default_duck_level(),
+            device_wait_secs: default_device_wait_secs(),
+            switch_retries: 0,
+            switch_retry_delay_ms: default_switch_retry_delay_ms(),
+            verify_switch: false,
+            state_role: Role::Console,
+            tray_shows_split: false,
+            show_mute_state: true,
+            log_level: LogLevel::Info,
+            log_format: LogFormat::Text,
+            volumes: BTreeMap::new(),
+            roles: BTreeMap::new(),
+            device_containers: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            active_profile: None,
+        }
+    }
+}
+
+impl Config {
+    /// Configured devices in cycling order.
+    pub fn device_ring(&self) -> Vec<&str> {
+        if self.devices.is_empty() {
+            vec![&self.speakers, &self.headphones]
+        } else {
+            self.devices.iter().map(String::as_str).collect()
+        }
+    }
+
+    /// Default roles that switching to `device_id` sets; all three unless `roles` says otherwise.
+    pub fn roles_for(&self, device_id: &str) -> &[Role] {
+        match self.roles.get(device_id) {
+            Some(roles) if !roles.is_empty() => roles,
+            _ => &Role::ALL,
+        }
+    }
+
+    /// Slot of `device_id` in the ring.
+    pub fn slot(&self, device_id: &str) -> Slot {
+        match self.device_ring().iter().position(|&id| id == device_id) {
+            Some(0) => Slot::Speakers,
+            Some(1) => Slot::Headphones,
+            _ => Slot::Other,
+        }
+    }
+
+    /// Device `step` places from `current_id` in the ring (wrapping), or the first device if
+    /// `current_id` isn't configured.
+    pub fn cycle_target(&self, current_id: &str, step: isize) -> &str {
+        ring_step(&self.device_ring(), current_id, step)
+    }
+
+    /// Microphone after `current_id` in `mics` (wrapping), or None if no mics are configured.
+    pub fn mic_target(&self, current_id: &str) -> Option<&str> {
+        let ring: Vec<&str> = self.mics.iter().map(String::as_str).collect();
+        (!ring.is_empty()).then(|| ring_step(&ring, current_id, 1))
+    }
+
+    /// Copy profile `name`'s pair into `speakers`/`headphones` and mark it active.
+    /// Returns false if there is no such profile.
+    pub fn activate_profile(&mut self, name: &str) -> bool {
+        let Some(profile) = self.profiles.get(name) else {
+            return false;
+        };
+        self.speakers = profile.speakers.clone();
+        self.headphones = profile.headphones.clone();
+        self.active_profile = Some(name.to_string());
+        true
+    }
+
+    /// Device for the first of `app_rules` matching the executable name `app`.
+    pub fn app_device(&self, app: &str) -> Option<&str> {
+        let normalize = |name: &str| name.to_lowercase().trim_end_matches(".exe").to_string();
+        let app = normalize(app);
+        self.app_rules
+            .iter()
+            .find(|rule| normalize(&rule.app) == app)
+            .map(|rule| rule.device.as_str())
+    }
+
+    /// Device ID to use for the given power source when `power_switch` is on.
+    pub fn power_device(&self, on_battery: bool) -> &str {
+        if on_battery {
+            self.battery_device.as_deref().unwrap_or(&self.headphones)
+        } else {
+            self.ac_device.as_deref().unwrap_or(&self.speakers)
+        }
+    }
+
+    /// Rewrite every hotkey in canonical form (see `hotkey::normalize`). Ones that don't parse
+    /// are left as written, so the user can still find and fix them.
+    pub fn normalize_hotkeys(&mut self) {
+        let normalize = |hotkey: &mut String| {
+            if let Ok(canonical) = hotkey::normalize(hotkey) {
+                *hotkey = canonical;
+            }
+        };
+        normalize(&mut self.hotkey);
+        let optional = [
+            &mut self.prev_hotkey,
+            &mut self.mic_hotkey,
+            &mut self.sound_settings_hotkey,
+            &mut self.mute_hotkey,
+            &mut self.peek_hotkey,
+        ];
+        optional.into_iter().flatten().for_each(normalize);
+        self.hotkeys.iter_mut().for_each(|binding| normalize(&mut binding.hotkey));
+        self.profiles.values_mut().filter_map(|p| p.hotkey.as_mut()).for_each(normalize);
+    }
+}
+
+/// Entry `step` places from `current_id` in a non-empty `ring` (wrapping), or the first entry
+/// if `current_id` isn't in it.
+fn ring_step<'a>(ring: &[&'a str], current_id: &str, step: isize) -> &'a str {
+    match ring.iter().position(|&id| id == current_id) {
+        Some(i) => ring[(i as isize + step).rem_euclid(ring.len() as isize) as usize],
+        None => ring[0],
+    }
 }
 
 fn default_true() -> bool {
     true
 }
 
-/// Path to the config file: %APPDATA%\AudioSwitcher\config.json
+/// Also used on first run, before there's a config to read it from.
+pub fn default_device_wait_secs() -> u32 {
+    15
+}
+
+fn default_state_role() -> Role {
+    Role::Console
+}
+
+fn default_click_action() -> ClickAction {
+    ClickAction::Toggle
+}
+
+fn default_debounce_ms() -> u64 {
+    250
+}
+
+fn default_duck_level() -> u8 {
+    30
+}
+
+fn default_switch_retry_delay_ms() -> u64 {
+    100
+}
+
+// Set by the --portable flag; a portable.txt next to the exe has the same effect
+static PORTABLE: AtomicBool = AtomicBool::new(false);
+
+/// Force portable mode (config next to the exe) for this process.
+pub fn set_portable() {
+    PORTABLE.store(true, Ordering::Release);
+}
+
+/// Directory of the exe when running portable (--portable or a portable.txt marker beside it).
+fn portable_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let dir = exe.parent()?;
+    (PORTABLE.load(Ordering::Acquire) || dir.join("portable.txt").exists()).then(|| dir.to_path_buf())
+}
+
+// Set by the --config flag; takes precedence over AUDIO_SWITCHER_CONFIG
+static CONFIG_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `path` as the config file for this process (the --config flag).
+pub fn set_config_override(path: PathBuf) {
+    let _ = CONFIG_OVERRIDE.set(path);
+}
+
+/// Config file given explicitly by --config or the AUDIO_SWITCHER_CONFIG environment variable.
+pub fn config_override() -> Option<PathBuf> {
+    CONFIG_OVERRIDE.get().cloned().or_else(|| {
+        std::env::var_os("AUDIO_SWITCHER_CONFIG")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+/// Whether config is stored next to the exe rather than in %APPDATA%.
+pub fn is_portable() -> bool {
+    portable_dir().is_some()
+}
+
+/// Path to the config file: the --config / AUDIO_SWITCHER_CONFIG override if given (used as-is),
+/// else %APPDATA%\AudioSwitcher\config.json, or config.json next to the exe in portable mode.
+/// A config.toml in the same directory takes precedence over config.json.
 pub fn config_path() -> PathBuf {
-    let mut path = dirs::config_dir().expect("Could not find AppData directory");
-    path.push("AudioSwitcher");
-    path.push("config.json");
-    path
+    if let Some(path) = config_override() {
+        return path;
+    }
+    let dir = portable_dir().unwrap_or_else(|| {
+        dirs::config_dir()
+            .expect("Could not find AppData directory")
+            .join("AudioSwitcher")
+    });
+    let toml_path = dir.join("config.toml");
+    if toml_path.exists() {
+        return toml_path;
+    }
+    dir.join("config.json")
 }
 
-/// Load config from disk. Returns None if file doesn't exist or is invalid.
-pub fn load() -> Option<Config> {
-    let path = config_path();
-    let data = fs::read_to_string(&path).ok()?;
-    serde_json::from_str(&data).ok()
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
 }
 
-/// Save config to disk, creating the directory if needed.
-pub fn save(config: &Config) {
+/// Load config from disk. Fails with `ConfigNotFound` on first run, or `ConfigInvalid` (with
+/// the parser's message) if the file exists but doesn't parse. A config from an older version
+/// is migrated and saved back.
+pub fn load() -> Result<Config> {
     let path = config_path();
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::ConfigNotFound(path)),
+        Err(e) => return Err(e.into()),
+    };
+    let (cfg, version) = parse_config(&path, &data)?;
+    if version < CONFIG_VERSION {
+        crate::log::info(&format!("Migrated config from version {} to {}", version, CONFIG_VERSION));
+        if let Err(e) = save(&cfg) {
+            crate::log::error(&format!("Failed to save migrated config: {}", e));
+        }
+    }
+    Ok(cfg)
+}
+
+/// Deserialize config file text, migrating it first if it's from an older version. Also
+/// returns the version the file was written with.
+fn parse_config(path: &Path, data: &str) -> Result<(Config, u32)> {
+    let invalid = |message: String| Error::ConfigInvalid { path: path.to_path_buf(), message };
+    let mut value: serde_json::Value = parse(path, data).map_err(invalid)?;
+
+    let version = stored_version(&value);
+    if version >= CONFIG_VERSION {
+        // Deserialize the text itself so errors keep their line numbers
+        return Ok((parse(path, data).map_err(invalid)?, version));
+    }
+
+    migrate(&mut value);
+    let cfg = serde_json::from_value(value).map_err(|e| invalid(e.to_string()))?;
+    Ok((cfg, version))
+}
+
+/// Deserialize config file text as JSON or TOML (by `path`'s extension).
+fn parse<T: serde::de::DeserializeOwned>(path: &Path, data: &str) -> std::result::Result<T, String> {
+    if is_toml(path) {
+        toml::from_str(data).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(data).map_err(|e| e.to_string())
+    }
+}
+
+/// The `version` a parsed config file was written with; 0 if it has none.
+fn stored_version(value: &serde_json::Value) -> u32 {
+    value.get("version").and_then(serde_json::Value::as_u64).map_or(0, |v| v as u32)
+}
+
+/// Upgrade a parsed config from an older version to `CONFIG_VERSION` in place, filling in
+/// what it lacks, so it deserializes as the current `Config`.
+pub fn migrate(value: &mut serde_json::Value) {
+    let version = stored_version(value);
+    let Some(fields) = value.as_object_mut() else {
+        return;
+    };
+
+    // 0 -> 1: versioning added. The oldest files are just device_a/device_b (read through the
+    // field aliases) and may predate the hotkey setting.
+    if version < 1 {
+        fields
+            .entry("hotkey")
+            .or_insert_with(|| Config::default().hotkey.into());
+    }
+
+    fields.insert("version".to_string(), CONFIG_VERSION.into());
+}
+
+/// Save config to disk in the format it was loaded from (JSON for new configs), creating the
+/// directory if needed.
+///
+/// The new contents go to a temporary file that's then renamed over the config, so a crash
+/// mid-write leaves the old file intact. The previous config is kept as `config.json.bak`;
+/// if that copy fails, the config isn't replaced.
+pub fn save(config: &Config) -> Result<()> {
+    save_to(&config_path(), config)
+}
+
+fn save_to(path: &Path, config: &Config) -> Result<()> {
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).expect("Failed to create config directory");
+        fs::create_dir_all(parent)?;
+    }
+    // Hotkeys are written in canonical form, however they were typed
+    let mut config = config.clone();
+    config.normalize_hotkeys();
+    let data = serialize(path, &config)?;
+    let tmp = path.with_extension(if is_toml(path) { "toml.tmp" } else { "json.tmp" });
+    fs::write(&tmp, &data)?;
+    if path.exists() {
+        fs::copy(path, backup_path(path))?;
+    }
+    fs::rename(&tmp, path)?;
+    *LAST_SAVED.lock().unwrap() = Some(data);
+    Ok(())
+}
+
+/// Config as JSON, or TOML if `path` ends in .toml.
+fn serialize(path: &Path, config: &Config) -> Result<String> {
+    Ok(if is_toml(path) {
+        toml::to_string_pretty(config)?
+    } else {
+        serde_json::to_string_pretty(config)?
+    })
+}
+
+/// Write a copy of the config to `dest` (TOML if it ends in .toml, else JSON).
+pub fn export(config: &Config, dest: &Path) -> Result<()> {
+    fs::write(dest, serialize(dest, config)?)?;
+    Ok(())
+}
+
+/// Replace the config with the one in `src` (JSON, or TOML if it ends in .toml), saved in the
+/// live config's format. `src` must parse as a `Config` (older versions are migrated) or
+/// nothing is touched. Returns where the replaced config was backed up, if there was one.
+pub fn import(src: &Path) -> Result<Option<PathBuf>> {
+    let data = fs::read_to_string(src)?;
+    let (cfg, _) = parse_config(src, &data)?;
+
+    // save backs the current config up before replacing it
+    let path = config_path();
+    let backup = path.exists().then(|| backup_path(&path));
+    save(&cfg)?;
+    Ok(backup)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension(if is_toml(path) { "toml.bak" } else { "json.bak" })
+}
+
+// Contents of our own last save, so the watcher doesn't report it as an outside edit
+static LAST_SAVED: Mutex<Option<String>> = Mutex::new(None);
+
+// How often the watcher checks the config file's modification time
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Call `on_change` (from a background thread) whenever the config file is modified by
+/// something other than `save`. The new contents may not parse; callers should check.
+pub fn watch(on_change: fn()) {
+    let path = config_path();
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified: Option<SystemTime> = modified(&path);
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            let current = modified(&path);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+            let Ok(data) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if LAST_SAVED.lock().unwrap().as_deref() != Some(data.as_str()) {
+                on_change();
+            }
+        }
+    });
+}
+
+/// Everything the app has stored that exists: the whole %APPDATA%\AudioSwitcher directory
+/// normally, or just the config, its backup, and the logs when they live beside the exe or at
+/// an overridden path (which may hold other files).
+pub fn stored_paths() -> Vec<PathBuf> {
+    let path = config_path();
+    let candidates = if config_override().is_none() && !is_portable() {
+        path.parent().map(Path::to_path_buf).into_iter().collect()
+    } else {
+        let log = crate::log::log_path();
+        vec![backup_path(&path), log.with_extension("log.1"), log, path]
+    };
+    candidates.into_iter().filter(|p| p.exists()).collect()
+}
+
+/// Move the config file aside to `config.json.bak` (or `config.toml.bak`), returning the
+/// backup path.
+pub fn reset() -> Result<PathBuf> {
+    let path = config_path();
+    let backup = backup_path(&path);
+    fs::rename(&path, &backup)?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_json_is_invalid_with_path() {
+        let path = Path::new("config.json");
+        let result = parse_config(path, r#"{ "speakers": "spk", "headphones": "#);
+        assert!(matches!(result, Err(Error::ConfigInvalid { path: p, message }) if p == path && !message.is_empty()));
+    }
+
+    #[test]
+    fn malformed_toml_is_invalid_with_path() {
+        let path = Path::new("config.toml");
+        let result = parse_config(path, "speakers = \"spk\"\nheadphones = ");
+        assert!(matches!(result, Err(Error::ConfigInvalid { path: p, .. }) if p == path));
+    }
+
+    #[test]
+    fn missing_field_is_invalid_with_path() {
+        let path = Path::new("config.json");
+        let result = parse_config(path, r#"{ "version": 1, "speakers": "spk", "hotkey": "Ctrl+Alt+S" }"#);
+        assert!(matches!(
+            result,
+            Err(Error::ConfigInvalid { path: p, message }) if p == path && message.contains("headphones")
+        ));
+    }
+
+    fn ring_config(ids: &[&str]) -> Config {
+        Config { devices: ids.iter().map(|id| id.to_string()).collect(), ..Default::default() }
+    }
+
+    #[test]
+    fn cycle_target_wraps_forwards() {
+        let cfg = ring_config(&["a", "b", "c"]);
+        assert_eq!(cfg.cycle_target("a", 1), "b");
+        assert_eq!(cfg.cycle_target("c", 1), "a");
+    }
+
+    #[test]
+    fn cycle_target_wraps_backwards() {
+        let cfg = ring_config(&["a", "b", "c"]);
+        assert_eq!(cfg.cycle_target("b", -1), "a");
+        assert_eq!(cfg.cycle_target("a", -1), "c");
+    }
+
+    #[test]
+    fn cycle_target_starts_over_when_current_is_not_in_ring() {
+        let cfg = ring_config(&["a", "b", "c"]);
+        assert_eq!(cfg.cycle_target("elsewhere", 1), "a");
+        assert_eq!(cfg.cycle_target("elsewhere", -1), "a");
+    }
+
+    #[test]
+    fn single_entry_ring_stays_put() {
+        let cfg = ring_config(&["a"]);
+        assert_eq!(cfg.cycle_target("a", 1), "a");
+        assert_eq!(cfg.cycle_target("a", -1), "a");
+        assert_eq!(cfg.cycle_target("elsewhere", 1), "a");
+    }
+
+    #[test]
+    fn save_keeps_backup_and_ignores_stale_tmp() {
+        let dir = std::env::temp_dir().join(format!("audio-switcher-save-{}", std::process::id()));
+        let path = dir.join("config.json");
+        let pair = |speakers: &str| Config {
+            speakers: speakers.to_string(),
+            headphones: "hp".to_string(),
+            ..Default::default()
+        };
+        let read = |path: &Path| parse_config(path, &fs::read_to_string(path).unwrap()).unwrap().0;
+
+        save_to(&path, &pair("first")).unwrap();
+        // A save that died after starting its temp file
+        fs::write(dir.join("config.json.tmp"), "{ \"speakers\": ").unwrap();
+        save_to(&path, &pair("second")).unwrap();
+
+        assert_eq!(read(&path).speakers, "second");
+        assert_eq!(read(&backup_path(&path)).speakers, "first");
+        assert!(!dir.join("config.json.tmp").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn migrate_fills_in_v0_config() {
+        let mut value = serde_json::json!({ "device_a": "spk", "device_b": "hp" });
+        migrate(&mut value);
+        assert_eq!(value["version"], CONFIG_VERSION);
+        assert_eq!(value["hotkey"], Config::default().hotkey);
+
+        // A v0 file that already has a hotkey keeps it
+        let mut value = serde_json::json!({ "device_a": "spk", "device_b": "hp", "hotkey": "Ctrl+F9" });
+        migrate(&mut value);
+        assert_eq!(value["hotkey"], "Ctrl+F9");
+    }
+
+    #[test]
+    fn v0_config_loads_as_current() {
+        let data = r#"{ "device_a": "spk", "device_b": "hp" }"#;
+        let (cfg, version) = parse_config(Path::new("config.json"), data).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(cfg.version, CONFIG_VERSION);
+        assert_eq!(cfg.speakers, "spk");
+        assert_eq!(cfg.headphones, "hp");
+        assert_eq!(cfg.hotkey, Config::default().hotkey);
+        assert_eq!(cfg.device_ring(), ["spk", "hp"]);
+    }
+
+    #[test]
+    fn normalize_hotkeys_covers_every_hotkey() {
+        let mut cfg = Config {
+            hotkey: "ctrl+alt+s".to_string(),
+            hotkeys: vec![DeviceHotkey { device: "spk".to_string(), hotkey: "alt+f1".to_string() }],
+            prev_hotkey: Some("ctrl+shift+s".to_string()),
+            mic_hotkey: Some("win+m".to_string()),
+            sound_settings_hotkey: Some("ctrl+alt+pgup".to_string()),
+            mute_hotkey: Some("Control+Banana".to_string()),
+            peek_hotkey: Some("shift+f12".to_string()),
+            ..Default::default()
+        };
+        cfg.profiles.insert(
+            "desk".to_string(),
+            Profile { speakers: "spk".to_string(), headphones: "hp".to_string(), hotkey: Some("alt+space".to_string()) },
+        );
+        cfg.normalize_hotkeys();
+
+        assert_eq!(cfg.hotkey, "Ctrl+Alt+S");
+        assert_eq!(cfg.hotkeys[0].hotkey, "Alt+F1");
+        assert_eq!(cfg.prev_hotkey.as_deref(), Some("Ctrl+Shift+S"));
+        assert_eq!(cfg.mic_hotkey.as_deref(), Some("Win+M"));
+        assert_eq!(cfg.sound_settings_hotkey.as_deref(), Some("Ctrl+Alt+PgUp"));
+        // Unparseable hotkeys are kept as written
+        assert_eq!(cfg.mute_hotkey.as_deref(), Some("Control+Banana"));
+        assert_eq!(cfg.peek_hotkey.as_deref(), Some("Shift+F12"));
+        assert_eq!(cfg.profiles["desk"].hotkey.as_deref(), Some("Alt+Space"));
     }
-    let data = serde_json::to_string_pretty(config).expect("Failed to serialize config");
-    fs::write(&path, data).expect("Failed to write config file");
 }
@@ -0,0 +1,112 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use windows::core::{w, PCWSTR};
+use windows::Win32::System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ};
+
+use crate::audio::{self, Direction, Role};
+use crate::config;
+use crate::error::Result;
+use crate::log;
+
+// How much of switcher.log goes into the report
+const LOG_TAIL_LINES: usize = 100;
+
+/// Write a plain-text support bundle (app/OS version, config, devices, defaults, recent log) to the
+/// Desktop, or the config directory if there's no Desktop. Returns the file's path.
+pub fn write_report() -> Result<PathBuf> {
+    let mut report = String::new();
+
+    let _ = writeln!(report, "Audio Output Switcher {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "OS: {}", os_version());
+    let _ = writeln!(report, "Portable: {}", config::is_portable());
+
+    let config_path = config::config_path();
+    let _ = writeln!(report, "\n== Config ({}) ==", config_path.display());
+    match fs::read_to_string(&config_path) {
+        Ok(contents) => report.push_str(contents.trim_end()),
+        Err(e) => {
+            let _ = write!(report, "(unreadable: {})", e);
+        }
+    }
+
+    for (heading, direction) in [("Output", Direction::Render), ("Input", Direction::Capture)] {
+        let _ = writeln!(report, "\n\n== {} defaults ==", heading);
+        for (label, role) in [
+            ("console", Role::Console),
+            ("multimedia", Role::Multimedia),
+            ("communications", Role::Communications),
+        ] {
+            let id = audio::get_default_device_id_for_role(direction, role)
+                .unwrap_or_else(|e| format!("(error: {})", e));
+            let _ = writeln!(report, "{}: {}", label, id);
+        }
+
+        let _ = writeln!(report, "\n== {} devices ==", heading);
+        match audio::list_all_devices(direction) {
+            Ok(devices) => {
+                for d in devices {
+                    let _ = writeln!(
+                        report,
+                        "{}\n  id: {}\n  state: {}, connected: {}, form factor: {}, container: {}",
+                        d.name,
+                        d.id,
+                        d.state.label(),
+                        d.connected,
+                        d.form_factor.label(),
+                        d.container_id.as_deref().unwrap_or("(system)"),
+                    );
+                }
+            }
+            Err(e) => {
+                let _ = writeln!(report, "(enumeration failed: {})", e);
+            }
+        }
+    }
+
+    // The tail of the switch log, if there is one
+    if let Ok(contents) = fs::read_to_string(log::log_path()) {
+        let lines: Vec<&str> = contents.lines().collect();
+        let _ = writeln!(report, "\n== Log (last {} lines) ==", LOG_TAIL_LINES.min(lines.len()));
+        for line in &lines[lines.len().saturating_sub(LOG_TAIL_LINES)..] {
+            let _ = writeln!(report, "{}", line);
+        }
+    }
+    report.push('\n');
+
+    let dir = dirs::desktop_dir()
+        .or_else(|| config_path.parent().map(|p| p.to_path_buf()))
+        .unwrap_or_default();
+    let path = dir.join("audio-output-switcher-diag.txt");
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// Product name, version, and build from the registry, e.g. "Windows 10 Pro 22H2 (build 19045)".
+fn os_version() -> String {
+    let product = reg_version_value(w!("ProductName")).unwrap_or_else(|| "Windows".to_string());
+    let display = reg_version_value(w!("DisplayVersion")).unwrap_or_default();
+    let build = reg_version_value(w!("CurrentBuild")).unwrap_or_else(|| "?".to_string());
+    format!("{} {} (build {})", product, display, build).replace("  ", " ")
+}
+
+fn reg_version_value(name: PCWSTR) -> Option<String> {
+    let mut buf = [0u16; 256];
+    let mut size = std::mem::size_of_val(&buf) as u32;
+    unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion"),
+            name,
+            RRF_RT_REG_SZ,
+            None,
+            Some(buf.as_mut_ptr() as *mut _),
+            Some(&mut size),
+        )
+        .ok()
+        .ok()?;
+    }
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    Some(String::from_utf16_lossy(&buf[..len]))
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors surfaced by the audio, config, hotkey, and tray modules.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing the config file failed.
+    ConfigIo(io::Error),
+    /// There's no config file yet (first run).
+    ConfigNotFound(PathBuf),
+    /// The config file exists but doesn't parse.
+    ConfigInvalid { path: PathBuf, message: String },
+    /// The config file couldn't be serialized or parsed.
+    ConfigFormat(serde_json::Error),
+    /// The config couldn't be written as TOML.
+    ConfigToml(toml::ser::Error),
+    /// A COM / Windows API call failed.
+    Com(windows::core::Error),
+    /// Listing the audio endpoints failed.
+    Enumerate(windows::core::Error),
+    /// There's no default endpoint in the requested direction (nothing enabled or plugged in).
+    NoDefaultDevice,
+    /// No audio endpoint exists with the given ID.
+    DeviceNotFound(String),
+    /// The endpoint exists but isn't active (unplugged, disabled, or not present).
+    DeviceUnavailable(String),
+    /// IPolicyConfig refused to change the default endpoint.
+    PolicyConfig(windows::core::Error),
+    /// The endpoint has no volume/mute control (some virtual devices).
+    NoVolumeControl(String),
+    /// The switch reported success, but Windows kept the old default.
+    SwitchNotApplied(String),
+    /// A hotkey string couldn't be parsed.
+    HotkeyParse(String),
+    /// Another program has already registered this hotkey.
+    HotkeyInUse(String),
+    /// ICO data is truncated or malformed.
+    IconInvalid(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ConfigIo(e) => write!(f, "config file error: {}", e),
+            Error::ConfigNotFound(path) => write!(f, "no config file at {}", path.display()),
+            Error::ConfigInvalid { path, message } => {
+                write!(f, "invalid config in {}: {}", path.display(), message)
+            }
+            Error::ConfigFormat(e) => write!(f, "invalid config: {}", e),
+            Error::ConfigToml(e) => write!(f, "invalid config: {}", e),
+            Error::Com(e) => write!(f, "Windows API error: {} ({})", e.message(), e.code()),
+            Error::Enumerate(e) => write!(f, "could not list audio devices: {} ({})", e.message(), e.code()),
+            Error::NoDefaultDevice => write!(f, "there is no default audio device (none enabled or plugged in)"),
+            Error::DeviceNotFound(id) => write!(f, "audio device not found: {}", id),
+            Error::DeviceUnavailable(id) => write!(f, "audio device is unplugged or disabled: {}", id),
+            Error::NoVolumeControl(id) => write!(f, "audio device has no volume control: {}", id),
+            Error::SwitchNotApplied(id) => write!(f, "Windows didn't make {} the default device", id),
+            Error::PolicyConfig(e) => {
+                write!(f, "could not change the default device: {} ({})", e.message(), e.code())
+            }
+            Error::HotkeyParse(msg) => write!(f, "{}", msg),
+            Error::HotkeyInUse(hotkey) => write!(f, "{} is already used by another program", hotkey),
+            Error::IconInvalid(msg) => write!(f, "invalid icon: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ConfigIo(e) => Some(e),
+            Error::ConfigFormat(e) => Some(e),
+            Error::ConfigToml(e) => Some(e),
+            Error::Com(e) | Error::PolicyConfig(e) | Error::Enumerate(e) => Some(e),
+            Error::ConfigNotFound(_) | Error::ConfigInvalid { .. } => None,
+            Error::NoDefaultDevice | Error::DeviceNotFound(_) | Error::DeviceUnavailable(_)
+            | Error::NoVolumeControl(_)
+            | Error::SwitchNotApplied(_)
+            | Error::HotkeyParse(_)
+            | Error::HotkeyInUse(_)
+            | Error::IconInvalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::ConfigIo(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::ConfigFormat(e)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self {
+        Error::ConfigToml(e)
+    }
+}
+
+impl From<windows::core::Error> for Error {
+    fn from(e: windows::core::Error) -> Self {
+        Error::Com(e)
+    }
+}
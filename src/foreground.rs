@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{CloseHandle, HWND};
+use windows::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows::Win32::UI::Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetWindowThreadProcessId, KillTimer, SetTimer, EVENT_SYSTEM_FOREGROUND, WINEVENT_OUTOFCONTEXT,
+};
+
+use crate::log;
+use crate::tray;
+
+// How long the foreground app has to stay put before its rule is applied, so alt-tabbing
+// through several windows doesn't switch devices on each one
+const SETTLE_MS: u32 = 1000;
+
+// HWINEVENTHOOK from SetWinEventHook (0 = not watching)
+static HOOK: AtomicIsize = AtomicIsize::new(0);
+
+// Thread timer restarted on every foreground change (0 = none pending)
+static SETTLE_TIMER: AtomicUsize = AtomicUsize::new(0);
+
+// Executable name of the app that most recently came to the foreground
+static FOREGROUND_APP: Mutex<Option<String>> = Mutex::new(None);
+
+/// Start or stop watching the foreground window. While watching, WM_APP_FOREGROUND is posted
+/// to the main loop once the foreground app has settled; `take_app` returns its name.
+///
+/// Must be called on the UI thread: the hook and timer callbacks run from its message loop.
+pub fn set_enabled(enabled: bool) {
+    let hook = HOOK.load(Ordering::Acquire);
+    if enabled && hook == 0 {
+        let hook = unsafe {
+            SetWinEventHook(
+                EVENT_SYSTEM_FOREGROUND,
+                EVENT_SYSTEM_FOREGROUND,
+                None,
+                Some(on_foreground),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT,
+            )
+        };
+        if hook.is_invalid() {
+            log::error(&format!("Failed to watch the foreground window: {}", windows::core::Error::from_win32()));
+        }
+        HOOK.store(hook.0 as isize, Ordering::Release);
+    } else if !enabled && hook != 0 {
+        HOOK.store(0, Ordering::Release);
+        unsafe {
+            let _ = UnhookWinEvent(HWINEVENTHOOK(hook as *mut _));
+            let timer = SETTLE_TIMER.swap(0, Ordering::AcqRel);
+            if timer != 0 {
+                let _ = KillTimer(None, timer);
+            }
+        }
+    }
+}
+
+/// Executable name (e.g. "chrome.exe") of the app that settled in the foreground.
+pub fn take_app() -> Option<String> {
+    FOREGROUND_APP.lock().unwrap().take()
+}
+
+unsafe extern "system" fn on_foreground(
+    _hook: HWINEVENTHOOK,
+    _event: u32,
+    hwnd: HWND,
+    _id_object: i32,
+    _id_child: i32,
+    _thread: u32,
+    _time: u32,
+) {
+    let Some(app) = process_name(hwnd) else {
+        return;
+    };
+    *FOREGROUND_APP.lock().unwrap() = Some(app);
+    // Passing the pending timer's ID restarts it instead of adding another
+    let timer = unsafe { SetTimer(None, SETTLE_TIMER.load(Ordering::Acquire), SETTLE_MS, Some(on_settled)) };
+    SETTLE_TIMER.store(timer, Ordering::Release);
+}
+
+unsafe extern "system" fn on_settled(_hwnd: HWND, _msg: u32, id: usize, _time: u32) {
+    unsafe {
+        let _ = KillTimer(None, id);
+    }
+    SETTLE_TIMER.store(0, Ordering::Release);
+    tray::post_to_main(tray::WM_APP_FOREGROUND);
+}
+
+/// File name of the executable that owns `hwnd`.
+fn process_name(hwnd: HWND) -> Option<String> {
+    let mut pid = 0;
+    unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+    if pid == 0 {
+        return None;
+    }
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut buf = [0u16; 260];
+        let mut len = buf.len() as u32;
+        let result = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buf.as_mut_ptr()), &mut len);
+        let _ = CloseHandle(process);
+        result.ok()?;
+        let path = String::from_utf16_lossy(&buf[..len as usize]);
+        path.rsplit('\\').next().map(str::to_string)
+    }
+}
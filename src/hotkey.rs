@@ -2,28 +2,83 @@ use windows::Win32::UI::Input::KeyboardAndMouse::{
     RegisterHotKey, UnregisterHotKey, HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT,
     MOD_SHIFT, MOD_WIN, VIRTUAL_KEY, VK_F1, VK_F10, VK_F11, VK_F12, VK_F2, VK_F3, VK_F4, VK_F5,
     VK_F6, VK_F7, VK_F8, VK_F9, VK_OEM_1, VK_OEM_2, VK_OEM_3, VK_OEM_4, VK_OEM_5, VK_OEM_6,
-    VK_OEM_7, VK_OEM_COMMA, VK_OEM_MINUS, VK_OEM_PERIOD, VK_OEM_PLUS, VK_SPACE,
+    VK_OEM_7, VK_OEM_COMMA, VK_OEM_MINUS, VK_OEM_PERIOD, VK_OEM_PLUS, VK_SPACE, VK_UP, VK_DOWN,
+    VK_LEFT, VK_RIGHT, VK_HOME, VK_END, VK_PRIOR, VK_NEXT, VK_INSERT, VK_DELETE, VK_TAB,
+    VK_ESCAPE, VK_RETURN, VK_NUMPAD0, GetAsyncKeyState, VK_CONTROL, VK_LCONTROL, VK_RCONTROL,
+    VK_MENU, VK_LMENU, VK_RMENU, VK_SHIFT, VK_LSHIFT, VK_RSHIFT, VK_LWIN, VK_RWIN,
+    VK_VOLUME_UP, VK_VOLUME_DOWN, VK_VOLUME_MUTE, VK_MEDIA_PLAY_PAUSE, VK_MEDIA_STOP,
+    VK_MEDIA_NEXT_TRACK, VK_MEDIA_PREV_TRACK, VK_LAUNCH_APP1, VK_LAUNCH_APP2,
 };
 
-const HOTKEY_TOGGLE: i32 = 1;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
 
-/// Parse a hotkey string like "Ctrl+Alt+S" into (modifiers, virtual_key).
-pub fn parse_hotkey(s: &str) -> Result<(HOT_KEY_MODIFIERS, VIRTUAL_KEY), String> {
+use windows::Win32::Foundation::{ERROR_HOTKEY_ALREADY_REGISTERED, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Threading::GetCurrentThreadId;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CallNextHookEx, DispatchMessageW, GetMessageW, KillTimer, PostQuitMessage, PostThreadMessageW,
+    SetTimer, SetWindowsHookExW, TranslateMessage, UnhookWindowsHookEx, HC_ACTION, KBDLLHOOKSTRUCT,
+    MSG, WH_KEYBOARD_LL, WM_KEYDOWN, WM_NULL, WM_SYSKEYDOWN, WM_TIMER,
+};
+
+use crate::error::{Error, Result};
+
+// Hotkey IDs (WM_HOTKEY wParam)
+pub const HOTKEY_TOGGLE: i32 = 1;
+pub const HOTKEY_PREV: i32 = 2;
+pub const HOTKEY_SOUND_SETTINGS: i32 = 3;
+pub const HOTKEY_MIC_TOGGLE: i32 = 4;
+pub const HOTKEY_MUTE: i32 = 5;
+pub const HOTKEY_PEEK: i32 = 6;
+// Profile hotkeys are HOTKEY_PROFILE_BASE + index into Config::profiles (name order)
+pub const HOTKEY_PROFILE_BASE: i32 = 100;
+// Per-device hotkeys are HOTKEY_DEVICE_BASE + index into Config::hotkeys
+pub const HOTKEY_DEVICE_BASE: i32 = 1000;
+
+// Throwaway ID for try_register_probe (top of the 0x0000-0xBFFF application range)
+const HOTKEY_PROBE: i32 = 0xBFFF;
+
+// IDs currently registered, so unregister() can release all of them
+static REGISTERED: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+// Whether the toggle/previous hotkeys auto-repeat while held (repeat_hotkey)
+static REPEAT_CYCLE: AtomicBool = AtomicBool::new(false);
+
+/// Let the toggle and previous-device hotkeys keep firing while held. Takes effect the next
+/// time they're registered; all other hotkeys always fire once per press.
+pub fn set_repeat_cycle(enabled: bool) {
+    REPEAT_CYCLE.store(enabled, Ordering::Release);
+}
+
+/// Parse a hotkey string like "Ctrl+Alt+S" into (modifiers, virtual_key). Modifiers are
+/// optional, so a bare key such as "Media_Next" is accepted too.
+///
+/// The `+` key itself is written last, as in "Ctrl++" or a bare "+"; it's the same physical
+/// key as `=`. Other empty parts (stray leading or trailing separators) are ignored.
+pub fn parse_hotkey(s: &str) -> Result<(HOT_KEY_MODIFIERS, VIRTUAL_KEY)> {
     let mut modifiers = MOD_NOREPEAT; // Prevent repeated firing when held
     let mut vk = VIRTUAL_KEY(0);
 
-    for part in s.split('+') {
-        match part.trim().to_uppercase().as_str() {
+    let s = s.trim();
+    let (s, plus_key) = match s.strip_suffix("++") {
+        Some(rest) => (rest, true),
+        None if s == "+" => ("", true),
+        None => (s, false),
+    };
+    let parts = s.split('+').map(str::trim).filter(|part| !part.is_empty());
+    for part in parts.chain(plus_key.then_some("=")) {
+        match part.to_uppercase().as_str() {
             "CTRL" | "CONTROL" => modifiers |= MOD_CONTROL,
             "ALT" => modifiers |= MOD_ALT,
             "SHIFT" => modifiers |= MOD_SHIFT,
             "WIN" | "WINDOWS" | "SUPER" => modifiers |= MOD_WIN,
             key => {
                 if vk != VIRTUAL_KEY(0) {
-                    return Err(format!(
+                    return Err(Error::HotkeyParse(format!(
                         "Multiple keys specified: already had one, got '{}'",
                         key
-                    ));
+                    )));
                 }
                 vk = key_name_to_vk(key)?;
             }
@@ -31,13 +86,36 @@ pub fn parse_hotkey(s: &str) -> Result<(HOT_KEY_MODIFIERS, VIRTUAL_KEY), String>
     }
 
     if vk == VIRTUAL_KEY(0) {
-        return Err("No key specified in hotkey string".to_string());
+        return Err(Error::HotkeyParse("No key specified in hotkey string".to_string()));
     }
 
     Ok((modifiers, vk))
 }
 
-fn key_name_to_vk(name: &str) -> Result<VIRTUAL_KEY, String> {
+/// Rewrite a hotkey string in canonical form ("ctrl+alt+pgup" -> "Ctrl+Alt+PgUp"), failing
+/// like `parse_hotkey` if it doesn't parse.
+pub fn normalize(s: &str) -> Result<String> {
+    let (modifiers, vk) = parse_hotkey(s)?;
+    Ok(vk_to_name(modifiers, vk))
+}
+
+/// Turn a parsed hotkey back into a string `parse_hotkey` accepts, with modifiers in
+/// Ctrl+Alt+Shift+Win order and the key's canonical name (e.g. "Ctrl+Shift+F5").
+pub fn vk_to_name(modifiers: HOT_KEY_MODIFIERS, vk: VIRTUAL_KEY) -> String {
+    let mut parts: Vec<String> = [(MOD_CONTROL, "Ctrl"), (MOD_ALT, "Alt"), (MOD_SHIFT, "Shift"), (MOD_WIN, "Win")]
+        .iter()
+        .filter(|&&(flag, _)| modifiers.contains(flag))
+        .map(|&(_, name)| name.to_string())
+        .collect();
+    parts.push(vk_to_key_name(vk));
+    parts.join("+")
+}
+
+/// Look up a key name, in any case ("f5", "F5", "PgUp", "pgup").
+fn key_name_to_vk(name: &str) -> Result<VIRTUAL_KEY> {
+    let name = name.to_uppercase();
+    let name = name.as_str();
+
     // Single letter A-Z -> ASCII value (0x41-0x5A)
     if name.len() == 1 {
         let ch = name.chars().next().unwrap();
@@ -49,6 +127,29 @@ fn key_name_to_vk(name: &str) -> Result<VIRTUAL_KEY, String> {
         }
     }
 
+    // Raw virtual-key code escape hatch: VK_0x5B (hex) or #91 (decimal)
+    let raw_code = if let Some(hex) = name.strip_prefix("VK_0X") {
+        Some(u16::from_str_radix(hex, 16))
+    } else {
+        name.strip_prefix('#').map(|dec| dec.parse::<u16>())
+    };
+    if let Some(code) = raw_code {
+        return match code {
+            Ok(code @ 0x01..=0xFE) => Ok(VIRTUAL_KEY(code)),
+            _ => Err(Error::HotkeyParse(format!(
+                "Invalid virtual-key code '{}': expected 0x01-0xFE (1-254)",
+                name
+            ))),
+        };
+    }
+
+    // Numpad digits: NUM0-NUM9 (or NUMPAD0-NUMPAD9)
+    if let Some(digit) = name.strip_prefix("NUMPAD").or_else(|| name.strip_prefix("NUM"))
+        && let [d @ b'0'..=b'9'] = digit.as_bytes()
+    {
+        return Ok(VIRTUAL_KEY(VK_NUMPAD0.0 + (d - b'0') as u16));
+    }
+
     // Function keys, special keys, and punctuation
     match name {
         "F1" => Ok(VK_F1),
@@ -64,6 +165,30 @@ fn key_name_to_vk(name: &str) -> Result<VIRTUAL_KEY, String> {
         "F11" => Ok(VK_F11),
         "F12" => Ok(VK_F12),
         "SPACE" => Ok(VK_SPACE),
+        "TAB" => Ok(VK_TAB),
+        "ESC" | "ESCAPE" => Ok(VK_ESCAPE),
+        "ENTER" | "RETURN" => Ok(VK_RETURN),
+        // Navigation keys
+        "UP" => Ok(VK_UP),
+        "DOWN" => Ok(VK_DOWN),
+        "LEFT" => Ok(VK_LEFT),
+        "RIGHT" => Ok(VK_RIGHT),
+        "HOME" => Ok(VK_HOME),
+        "END" => Ok(VK_END),
+        "PGUP" | "PAGEUP" => Ok(VK_PRIOR),
+        "PGDN" | "PAGEDOWN" => Ok(VK_NEXT),
+        "INS" | "INSERT" => Ok(VK_INSERT),
+        "DEL" | "DELETE" => Ok(VK_DELETE),
+        // Media and launch keys (usually bound without modifiers)
+        "VOLUP" => Ok(VK_VOLUME_UP),
+        "VOLDOWN" => Ok(VK_VOLUME_DOWN),
+        "MUTE" => Ok(VK_VOLUME_MUTE),
+        "MEDIA_PLAY" => Ok(VK_MEDIA_PLAY_PAUSE),
+        "MEDIA_STOP" => Ok(VK_MEDIA_STOP),
+        "MEDIA_NEXT" => Ok(VK_MEDIA_NEXT_TRACK),
+        "MEDIA_PREV" => Ok(VK_MEDIA_PREV_TRACK),
+        "LAUNCH_APP1" => Ok(VK_LAUNCH_APP1),
+        "LAUNCH_APP2" => Ok(VK_LAUNCH_APP2),
         // Punctuation / OEM keys
         "\\" => Ok(VK_OEM_5),
         "/" => Ok(VK_OEM_2),
@@ -76,22 +201,406 @@ fn key_name_to_vk(name: &str) -> Result<VIRTUAL_KEY, String> {
         "," => Ok(VK_OEM_COMMA),
         "." => Ok(VK_OEM_PERIOD),
         "`" => Ok(VK_OEM_3),
-        _ => Err(format!("Unknown key: '{}'", name)),
+        _ => Err(Error::HotkeyParse(format!(
+            "Unknown key: '{}'. Use a letter, digit, F1-F12, NUM0-NUM9, Up/Down/Left/Right, \
+             Home/End/PgUp/PgDn/Ins/Del, Space/Tab/Esc/Enter, VolUp/VolDown/Mute, \
+             Media_Play/Media_Stop/Media_Next/Media_Prev, Launch_App1/Launch_App2, punctuation, \
+             or a raw code like VK_0x5B or #91",
+            name
+        ))),
     }
 }
 
-/// Register the user's audio toggle hotkey (global).
-pub fn register(hotkey_str: &str) -> Result<(), String> {
-    let (modifiers, vk) = parse_hotkey(hotkey_str)?;
+/// Register a global hotkey under `id` (one of the HOTKEY_* constants).
+pub fn register(id: i32, hotkey_str: &str) -> Result<()> {
+    register_raw(id, hotkey_str)?;
+    REGISTERED.lock().unwrap().push(id);
+    Ok(())
+}
+
+/// RegisterHotKey, reporting a combination owned by another program as `HotkeyInUse`.
+fn register_raw(id: i32, hotkey_str: &str) -> Result<()> {
+    let (mut modifiers, vk) = parse_hotkey(hotkey_str)?;
+    if matches!(id, HOTKEY_TOGGLE | HOTKEY_PREV) && REPEAT_CYCLE.load(Ordering::Acquire) {
+        modifiers &= !MOD_NOREPEAT;
+    }
+    match unsafe { RegisterHotKey(None, id, modifiers, vk.0 as u32) } {
+        Ok(()) => Ok(()),
+        Err(e) if e.code() == ERROR_HOTKEY_ALREADY_REGISTERED.to_hresult() => {
+            Err(Error::HotkeyInUse(hotkey_str.to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Register each `(id, hotkey_str)` pair, carrying on past failures. Returns the ones that
+/// failed along with why.
+pub fn register_all<'a>(hotkeys: &[(i32, &'a str)]) -> Vec<(i32, &'a str, Error)> {
+    hotkeys
+        .iter()
+        .filter_map(|&(id, hotkey_str)| register(id, hotkey_str).err().map(|e| (id, hotkey_str, e)))
+        .collect()
+}
+
+/// Check that `hotkey_str` can actually be registered right now (i.e. no other program owns
+/// it) by registering it under a throwaway ID and releasing it straight away.
+pub fn try_register_probe(hotkey_str: &str) -> Result<()> {
+    register_raw(HOTKEY_PROBE, hotkey_str)?;
     unsafe {
-        RegisterHotKey(None, HOTKEY_TOGGLE, modifiers, vk.0 as u32)
-            .map_err(|e| format!("Failed to register hotkey '{}': {}", hotkey_str, e))
+        let _ = UnregisterHotKey(None, HOTKEY_PROBE);
     }
+    Ok(())
 }
 
 /// Unregister all hotkeys.
 pub fn unregister() {
+    for id in REGISTERED.lock().unwrap().drain(..) {
+        unsafe {
+            let _ = UnregisterHotKey(None, id);
+        }
+    }
+}
+
+// Result of an in-progress capture(): None while waiting, Some(None) if cancelled
+static CAPTURED: Mutex<Option<Option<String>>> = Mutex::new(None);
+static CAPTURE_THREAD: AtomicU32 = AtomicU32::new(0);
+// Modifier keys held during a capture, one bit per MODIFIER_KEYS entry. The hook swallows
+// their presses, so GetAsyncKeyState never sees them go down and the hook tracks them itself.
+static CAPTURE_HELD: AtomicU32 = AtomicU32::new(0);
+
+// How long capture() waits for a combination before giving up
+const CAPTURE_TIMEOUT_MS: u32 = 10_000;
+
+const MODIFIER_KEYS: [(VIRTUAL_KEY, HOT_KEY_MODIFIERS); 11] = [
+    (VK_CONTROL, MOD_CONTROL),
+    (VK_LCONTROL, MOD_CONTROL),
+    (VK_RCONTROL, MOD_CONTROL),
+    (VK_MENU, MOD_ALT),
+    (VK_LMENU, MOD_ALT),
+    (VK_RMENU, MOD_ALT),
+    (VK_SHIFT, MOD_SHIFT),
+    (VK_LSHIFT, MOD_SHIFT),
+    (VK_RSHIFT, MOD_SHIFT),
+    (VK_LWIN, MOD_WIN),
+    (VK_RWIN, MOD_WIN),
+];
+
+/// Wait for the user to press a key combination and return it as a string `parse_hotkey`
+/// accepts, e.g. "Ctrl+Alt+S". Returns None if they press Esc, nothing is pressed within
+/// 10 seconds, `cancel_capture` is called, or the hook can't be installed.
+///
+/// Uses a temporary low-level keyboard hook, which swallows keystrokes until it's removed.
+/// Blocks, pumping this thread's messages, until one of the above happens.
+pub fn capture() -> Option<String> {
+    *CAPTURED.lock().unwrap() = None;
+    // Modifiers already down before the hook goes in are still visible to GetAsyncKeyState
+    let held = MODIFIER_KEYS
+        .iter()
+        .enumerate()
+        .filter(|(_, (key, _))| unsafe { GetAsyncKeyState(key.0 as i32) } as u16 & 0x8000 != 0)
+        .fold(0, |held, (i, _)| held | (1 << i));
+    CAPTURE_HELD.store(held, Ordering::Release);
     unsafe {
-        let _ = UnregisterHotKey(None, HOTKEY_TOGGLE);
+        CAPTURE_THREAD.store(GetCurrentThreadId(), Ordering::Release);
+        let module = GetModuleHandleW(None).ok()?;
+        let hook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(capture_proc), Some(module.into()), 0).ok()?;
+        let timer = SetTimer(None, 0, CAPTURE_TIMEOUT_MS, None);
+
+        let mut msg = MSG::default();
+        while CAPTURED.lock().unwrap().is_none() {
+            match GetMessageW(&mut msg, None, 0, 0).0 {
+                // WM_QUIT: post it again so the caller's loop sees it too
+                0 => {
+                    PostQuitMessage(msg.wParam.0 as i32);
+                    break;
+                }
+                -1 => break,
+                _ if msg.message == WM_TIMER && msg.hwnd.0.is_null() && msg.wParam.0 == timer => break,
+                _ => {
+                    let _ = TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            }
+        }
+
+        let _ = KillTimer(None, timer);
+        let _ = UnhookWindowsHookEx(hook);
+    }
+    CAPTURED.lock().unwrap().take().flatten()
+}
+
+/// End a `capture` in progress as if Esc had been pressed, e.g. because the window that
+/// started it is being destroyed. Call it on the capturing thread, from a message handler.
+pub fn cancel_capture() {
+    let mut captured = CAPTURED.lock().unwrap();
+    if captured.is_none() {
+        *captured = Some(None);
+    }
+}
+
+unsafe extern "system" fn capture_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code != HC_ACTION as i32 {
+        return unsafe { CallNextHookEx(None, code, wparam, lparam) };
+    }
+    let event = unsafe { &*(lparam.0 as *const KBDLLHOOKSTRUCT) };
+    let is_down = matches!(wparam.0 as u32, WM_KEYDOWN | WM_SYSKEYDOWN);
+    let vk = VIRTUAL_KEY(event.vkCode as u16);
+
+    // A modifier on its own isn't a hotkey; note it for the key that follows
+    if let Some(i) = MODIFIER_KEYS.iter().position(|&(key, _)| key == vk) {
+        if is_down {
+            CAPTURE_HELD.fetch_or(1 << i, Ordering::AcqRel);
+        } else {
+            CAPTURE_HELD.fetch_and(!(1 << i), Ordering::AcqRel);
+        }
+        return LRESULT(1);
+    }
+
+    let mut captured = CAPTURED.lock().unwrap();
+    if is_down && captured.is_none() {
+        let held = CAPTURE_HELD.load(Ordering::Acquire);
+        let modifiers = MODIFIER_KEYS
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| held & (1 << i) != 0)
+            .fold(HOT_KEY_MODIFIERS(0), |modifiers, (_, &(_, flag))| modifiers | flag);
+
+        *captured = if vk == VK_ESCAPE && modifiers == HOT_KEY_MODIFIERS(0) {
+            Some(None)
+        } else {
+            Some(Some(vk_to_name(modifiers, vk)))
+        };
+        // Wake capture()'s GetMessageW so it sees the result
+        unsafe {
+            let _ = PostThreadMessageW(CAPTURE_THREAD.load(Ordering::Acquire), WM_NULL, WPARAM(0), LPARAM(0));
+        }
+    }
+
+    // Swallow everything so the combination doesn't reach other programs
+    LRESULT(1)
+}
+
+/// The inverse of key_name_to_vk, falling back to the raw #code form.
+fn vk_to_key_name(vk: VIRTUAL_KEY) -> String {
+    let name = match vk {
+        VIRTUAL_KEY(code @ (0x30..=0x39 | 0x41..=0x5A)) => return (code as u8 as char).to_string(),
+        VIRTUAL_KEY(code) if (VK_NUMPAD0.0..=VK_NUMPAD0.0 + 9).contains(&code) => {
+            return format!("Num{}", code - VK_NUMPAD0.0);
+        }
+        VIRTUAL_KEY(code) if (VK_F1.0..=VK_F12.0).contains(&code) => {
+            return format!("F{}", code - VK_F1.0 + 1);
+        }
+        VK_SPACE => "Space",
+        VK_TAB => "Tab",
+        VK_ESCAPE => "Esc",
+        VK_RETURN => "Enter",
+        VK_UP => "Up",
+        VK_DOWN => "Down",
+        VK_LEFT => "Left",
+        VK_RIGHT => "Right",
+        VK_HOME => "Home",
+        VK_END => "End",
+        VK_PRIOR => "PgUp",
+        VK_NEXT => "PgDn",
+        VK_INSERT => "Ins",
+        VK_DELETE => "Del",
+        VK_VOLUME_UP => "VolUp",
+        VK_VOLUME_DOWN => "VolDown",
+        VK_VOLUME_MUTE => "Mute",
+        VK_MEDIA_PLAY_PAUSE => "Media_Play",
+        VK_MEDIA_STOP => "Media_Stop",
+        VK_MEDIA_NEXT_TRACK => "Media_Next",
+        VK_MEDIA_PREV_TRACK => "Media_Prev",
+        VK_LAUNCH_APP1 => "Launch_App1",
+        VK_LAUNCH_APP2 => "Launch_App2",
+        VK_OEM_5 => "\\",
+        VK_OEM_2 => "/",
+        VK_OEM_1 => ";",
+        VK_OEM_7 => "'",
+        VK_OEM_4 => "[",
+        VK_OEM_6 => "]",
+        VK_OEM_MINUS => "-",
+        VK_OEM_PLUS => "=",
+        VK_OEM_COMMA => ",",
+        VK_OEM_PERIOD => ".",
+        VK_OEM_3 => "`",
+        VIRTUAL_KEY(code) => return format!("#{}", code),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        VK_NUMPAD1, VK_NUMPAD2, VK_NUMPAD3, VK_NUMPAD4, VK_NUMPAD5, VK_NUMPAD6, VK_NUMPAD7, VK_NUMPAD8,
+        VK_NUMPAD9,
+    };
+
+    fn is_parse_error<T>(result: Result<T>) -> bool {
+        matches!(result, Err(Error::HotkeyParse(_)))
+    }
+
+    #[test]
+    fn raw_hex_and_decimal_codes() {
+        let ctrl_alt = MOD_NOREPEAT | MOD_CONTROL | MOD_ALT;
+        assert_eq!(parse_hotkey("Ctrl+Alt+VK_0x5B").unwrap(), (ctrl_alt, VIRTUAL_KEY(0x5B)));
+        assert_eq!(parse_hotkey("Ctrl+Alt+#91").unwrap(), (ctrl_alt, VIRTUAL_KEY(91)));
+        assert_eq!(parse_hotkey("Ctrl+VK_0x01").unwrap().1, VIRTUAL_KEY(0x01));
+        assert_eq!(parse_hotkey("Ctrl+#254").unwrap().1, VIRTUAL_KEY(254));
+    }
+
+    #[test]
+    fn raw_codes_ignore_case() {
+        for hotkey in ["Ctrl+vk_0x5b", "Ctrl+VK_0X5B", "Ctrl+Vk_0x5B"] {
+            assert_eq!(parse_hotkey(hotkey).unwrap().1, VIRTUAL_KEY(0x5B), "{}", hotkey);
+        }
+    }
+
+    #[test]
+    fn raw_codes_out_of_range() {
+        for key in ["VK_0x00", "#0", "VK_0xFF", "#255", "VK_0x100", "#256", "#70000"] {
+            assert!(is_parse_error(parse_hotkey(&format!("Ctrl+{}", key))), "{}", key);
+        }
+    }
+
+    #[test]
+    fn raw_codes_not_numeric() {
+        for key in ["VK_0x", "VK_0xZZ", "#", "#abc", "#-1", "#0x5B"] {
+            assert!(is_parse_error(parse_hotkey(&format!("Ctrl+{}", key))), "{}", key);
+        }
+    }
+
+    #[test]
+    fn numpad_keys() {
+        let expected = [
+            VK_NUMPAD0, VK_NUMPAD1, VK_NUMPAD2, VK_NUMPAD3, VK_NUMPAD4, VK_NUMPAD5, VK_NUMPAD6, VK_NUMPAD7,
+            VK_NUMPAD8, VK_NUMPAD9,
+        ];
+        for (digit, vk) in expected.into_iter().enumerate() {
+            assert_eq!(key_name_to_vk(&format!("NUM{}", digit)).unwrap(), vk);
+            assert_eq!(key_name_to_vk(&format!("NUMPAD{}", digit)).unwrap(), vk);
+        }
+        assert!(is_parse_error(key_name_to_vk("NUM10")));
+        assert!(is_parse_error(key_name_to_vk("NUM")));
+    }
+
+    #[test]
+    fn navigation_keys() {
+        let expected = [
+            ("UP", VK_UP),
+            ("DOWN", VK_DOWN),
+            ("LEFT", VK_LEFT),
+            ("RIGHT", VK_RIGHT),
+            ("HOME", VK_HOME),
+            ("END", VK_END),
+            ("PGUP", VK_PRIOR),
+            ("PGDN", VK_NEXT),
+            ("INS", VK_INSERT),
+            ("DEL", VK_DELETE),
+        ];
+        for (name, vk) in expected {
+            assert_eq!(key_name_to_vk(name).unwrap(), vk, "{}", name);
+        }
+    }
+
+    #[test]
+    fn tab_esc_enter() {
+        for (name, vk) in [("TAB", VK_TAB), ("ESC", VK_ESCAPE), ("ENTER", VK_RETURN)] {
+            assert_eq!(key_name_to_vk(name).unwrap(), vk, "{}", name);
+        }
+    }
+
+    #[test]
+    fn unknown_key_lists_valid_names() {
+        let Err(Error::HotkeyParse(message)) = key_name_to_vk("BANANA") else {
+            panic!("BANANA parsed as a key");
+        };
+        assert!(message.contains("NUM0-NUM9") && message.contains("Up/Down/Left/Right"), "{}", message);
+    }
+
+    #[test]
+    fn media_and_launch_keys() {
+        let expected = [
+            ("VolUp", VK_VOLUME_UP),
+            ("VolDown", VK_VOLUME_DOWN),
+            ("Mute", VK_VOLUME_MUTE),
+            ("Media_Play", VK_MEDIA_PLAY_PAUSE),
+            ("Media_Stop", VK_MEDIA_STOP),
+            ("Media_Next", VK_MEDIA_NEXT_TRACK),
+            ("Media_Prev", VK_MEDIA_PREV_TRACK),
+            ("Launch_App1", VK_LAUNCH_APP1),
+            ("Launch_App2", VK_LAUNCH_APP2),
+        ];
+        for (name, vk) in expected {
+            // Bare, with no modifiers besides the implicit no-repeat
+            assert_eq!(parse_hotkey(name).unwrap(), (MOD_NOREPEAT, vk), "{}", name);
+            assert_eq!(parse_hotkey(&format!("Ctrl+{}", name)).unwrap(), (MOD_NOREPEAT | MOD_CONTROL, vk), "{}", name);
+        }
+    }
+
+    #[test]
+    fn plus_key() {
+        assert_eq!(parse_hotkey("Ctrl++").unwrap(), (MOD_NOREPEAT | MOD_CONTROL, VK_OEM_PLUS));
+        assert_eq!(parse_hotkey("Ctrl+Shift++").unwrap(), (MOD_NOREPEAT | MOD_CONTROL | MOD_SHIFT, VK_OEM_PLUS));
+        assert_eq!(parse_hotkey("Alt+=").unwrap(), (MOD_NOREPEAT | MOD_ALT, VK_OEM_PLUS));
+        assert_eq!(parse_hotkey("+").unwrap(), (MOD_NOREPEAT, VK_OEM_PLUS));
+    }
+
+    #[test]
+    fn stray_separators_are_ignored() {
+        let ctrl_alt = MOD_NOREPEAT | MOD_CONTROL | MOD_ALT;
+        assert_eq!(parse_hotkey("+Ctrl+Alt+S").unwrap(), (ctrl_alt, VIRTUAL_KEY(b'S' as u16)));
+        assert_eq!(parse_hotkey("Ctrl+ +;").unwrap(), (MOD_NOREPEAT | MOD_CONTROL, VK_OEM_1));
+    }
+
+    #[test]
+    fn trailing_separator_without_key() {
+        assert!(is_parse_error(parse_hotkey("Ctrl+")));
+        assert!(is_parse_error(parse_hotkey("Ctrl+Alt+")));
+        assert!(is_parse_error(parse_hotkey("")));
+    }
+
+    #[test]
+    fn plus_and_another_key() {
+        assert!(is_parse_error(parse_hotkey("Ctrl+S++")));
+    }
+
+    #[test]
+    fn normalize_canonical_names() {
+        let expected = [
+            ("f5", "F5"),
+            ("F5", "F5"),
+            ("space", "Space"),
+            ("Space", "Space"),
+            ("pgup", "PgUp"),
+            ("PgUp", "PgUp"),
+            ("PageUp", "PgUp"),
+            ("ctrl+alt+s", "Ctrl+Alt+S"),
+            ("win+shift+ctrl+num5", "Ctrl+Shift+Win+Num5"),
+            ("Control+Escape", "Ctrl+Esc"),
+            ("Ctrl++", "Ctrl+="),
+            ("alt+vk_0x5b", "Alt+#91"),
+        ];
+        for (input, canonical) in expected {
+            assert_eq!(normalize(input).unwrap(), canonical, "{}", input);
+        }
+        assert!(is_parse_error(normalize("Ctrl+Banana")));
+    }
+
+    #[test]
+    fn vk_to_name_round_trips() {
+        let modifier_sets = [
+            MOD_NOREPEAT,
+            MOD_NOREPEAT | MOD_CONTROL | MOD_ALT,
+            MOD_NOREPEAT | MOD_SHIFT | MOD_WIN,
+        ];
+        for modifiers in modifier_sets {
+            for code in 0x01..=0xFE {
+                let vk = VIRTUAL_KEY(code);
+                let name = vk_to_name(modifiers, vk);
+                assert_eq!(parse_hotkey(&name).unwrap(), (modifiers, vk), "{}", name);
+            }
+        }
     }
 }
@@ -0,0 +1,142 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+
+use windows::Win32::System::SystemInformation::GetLocalTime;
+
+use crate::config::{self, LogFormat, LogLevel};
+
+// Once switcher.log grows past this, it's moved to switcher.log.1 and a new one started
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+// log_format = "json": one JSON object per line instead of the text format
+static JSON: AtomicBool = AtomicBool::new(false);
+
+// Serializes writes from the UI and device-notification threads
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Release);
+}
+
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Release);
+}
+
+/// What started a switch, recorded with each switch event.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Hotkey,
+    Cli,
+    Tray,
+    Pipe,
+    /// Switched by the app itself: startup device, power source, or a device being connected.
+    Auto,
+}
+
+impl Trigger {
+    fn as_str(self) -> &'static str {
+        match self {
+            Trigger::Hotkey => "hotkey",
+            Trigger::Cli => "cli",
+            Trigger::Tray => "tray",
+            Trigger::Pipe => "pipe",
+            Trigger::Auto => "auto",
+        }
+    }
+}
+
+/// Record a switch from `from` to `to` (device names): at info level if it worked, at error
+/// level with the reason if it didn't.
+pub fn switch(from: &str, to: &str, trigger: Trigger, error: Option<&str>) {
+    let level = if error.is_some() { LogLevel::Error } else { LogLevel::Info };
+    if JSON.load(Ordering::Acquire) {
+        let mut event = serde_json::json!({
+            "from_device": from,
+            "to_device": to,
+            "trigger": trigger.as_str(),
+            "success": error.is_none(),
+        });
+        if let Some(error) = error {
+            event["error"] = error.into();
+        }
+        write_entry(level, event);
+    } else {
+        match error {
+            None => write(level, &format!("Switched {} -> {} ({})", from, to, trigger.as_str())),
+            Some(e) => write(level, &format!("Switch {} -> {} ({}) failed: {}", from, to, trigger.as_str(), e)),
+        }
+    }
+}
+
+pub fn error(msg: &str) {
+    write(LogLevel::Error, msg);
+}
+
+pub fn info(msg: &str) {
+    write(LogLevel::Info, msg);
+}
+
+pub fn debug(msg: &str) {
+    write(LogLevel::Debug, msg);
+}
+
+/// Path of the log file: switcher.log beside the config file.
+pub fn log_path() -> PathBuf {
+    config::config_path().with_file_name("switcher.log")
+}
+
+/// Append a timestamped line if `level` is enabled. Logging failures are ignored.
+fn write(level: LogLevel, msg: &str) {
+    write_entry(level, serde_json::json!({ "message": msg }));
+}
+
+/// Append `fields` as a timestamped line: as a JSON object (with `timestamp` and `level` added)
+/// in the JSON format, otherwise just its `message`.
+fn write_entry(level: LogLevel, mut fields: serde_json::Value) {
+    if level == LogLevel::Off || level as u8 > LEVEL.load(Ordering::Acquire) {
+        return;
+    }
+
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = log_path();
+    if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_SIZE) {
+        let _ = fs::rename(&path, path.with_extension("log.1"));
+    }
+
+    let t = unsafe { GetLocalTime() };
+    let label = match level {
+        LogLevel::Error => "ERROR",
+        LogLevel::Debug => "DEBUG",
+        _ => "INFO",
+    };
+    let line = if JSON.load(Ordering::Acquire) {
+        fields["timestamp"] = format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}",
+            t.wYear, t.wMonth, t.wDay, t.wHour, t.wMinute, t.wSecond, t.wMilliseconds
+        )
+        .into();
+        fields["level"] = label.to_lowercase().into();
+        fields.to_string()
+    } else {
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} [{}] {}",
+            t.wYear,
+            t.wMonth,
+            t.wDay,
+            t.wHour,
+            t.wMinute,
+            t.wSecond,
+            t.wMilliseconds,
+            label,
+            fields["message"].as_str().unwrap_or_default()
+        )
+    };
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
@@ -1,72 +1,280 @@
 #![windows_subsystem = "windows"]
 
 mod audio;
+mod autostart;
 mod config;
+mod diag;
+mod error;
+mod foreground;
 mod hotkey;
+mod log;
+mod overlay;
+mod pipe;
+mod power;
+mod setup_dialog;
+mod toast;
 mod tray;
 
+use std::fs;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use windows::core::PCWSTR;
-use windows::Win32::Foundation::{LPARAM, WPARAM};
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{
+    GetLastError, ERROR_ALREADY_EXISTS, HANDLE, LPARAM, RPC_E_CHANGED_MODE, WPARAM,
+};
 use windows::Win32::Media::Audio::{PlaySoundW, SND_ASYNC, SND_FILENAME, SND_MEMORY};
 use windows::Win32::System::Com::{CoInitializeEx, COINIT_APARTMENTTHREADED};
 use windows::Win32::System::Console::{AllocConsole, FreeConsole};
+use windows::Win32::System::Threading::CreateMutexW;
 use windows::Win32::UI::WindowsAndMessaging::{
-    DispatchMessageW, FindWindowW, GetMessageW, SendMessageW, MSG, WM_HOTKEY,
+    DispatchMessageW, FindWindowW, GetMessageW, MessageBoxW, SendMessageW, IDYES, MB_ICONERROR,
+    MB_ICONWARNING, MB_OK, MB_YESNO, MSG, WM_HOTKEY,
 };
 
-// Hotkey IDs
-const HOTKEY_TOGGLE: i32 = 1;
+// With repeat_hotkey, the fastest a held hotkey moves through the devices
+const HOLD_CYCLE_INTERVAL: Duration = Duration::from_millis(500);
 
 // Embedded switch sound (default)
 const SWITCH_SOUND: &[u8] = include_bytes!("../assets/Windows Background.wav");
+// Embedded default when switching to the headphones, so the two are distinguishable by ear
+const HEADPHONES_SOUND: &[u8] = include_bytes!("../assets/audio_switched_1_quieter.wav");
+// Embedded low double beep for a switch that failed
+const FAILED_SOUND: &[u8] = include_bytes!("../assets/switch_failed.wav");
+
+/// Which sound play_switch_sound plays.
+#[derive(Clone, Copy)]
+enum SwitchSound {
+    /// Switched to the device in this slot.
+    Switched(config::Slot),
+    /// The switch didn't happen.
+    Failed,
+}
 
 // Flag to signal reconfigure request from the message loop
 static RECONFIGURE: AtomicBool = AtomicBool::new(false);
 
+// Set by --quiet: no switch sound for this invocation, whatever the config says
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// Current and previous default device, updated from endpoint notifications (for "mru" toggle)
+static DEFAULT_HISTORY: Mutex<DefaultHistory> = Mutex::new(DefaultHistory {
+    current: None,
+    previous: None,
+});
+
+// Custom WAVs from the config, played instead of notify.wav / the embedded sounds
+static SOUND_PATHS: Mutex<SoundPaths> = Mutex::new(SoundPaths { any: None, speakers: None, headphones: None });
+
+struct SoundPaths {
+    // notify_sound_path
+    any: Option<PathBuf>,
+    speakers: Option<PathBuf>,
+    headphones: Option<PathBuf>,
+}
+
+// Device last written to the config's last_device (for restore_on_start)
+static LAST_DEVICE: Mutex<Option<String>> = Mutex::new(None);
+
+// Endpoints reported by the notification thread, in order, drained on WM_APP_DEVICE_ARRIVED.
+// Several can arrive before the UI thread gets to the first message (e.g. a dock's devices).
+static ARRIVED_DEVICES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Set while a WM_APP_DEFAULT_CHANGED is queued, so a burst of changes posts only one
+static DEFAULT_CHANGE_QUEUED: AtomicBool = AtomicBool::new(false);
+
+struct DefaultHistory {
+    current: Option<String>,
+    previous: Option<String>,
+}
+
+const HELP: &str = "\
+Usage: audio-output-switcher.exe [--portable] [--config <path>] [--no-tray] [--quiet] [command]
+
+With no command, runs in the system tray (or, with --no-tray, in the background with
+only the hotkeys). --quiet skips the switch sound for this one command, and needs one.
+
+Commands:
+  toggle [--role <role>]  Switch to the next configured device (role: console, multimedia,
+                          communications decides which default counts as current)
+  speakers                Switch to the speakers
+  headphones              Switch to the headphones
+  next, prev              Move forwards or backwards through the configured devices
+  device <n>              Switch to the n-th configured device
+  set <name or n>         Switch to any active device by part of its name or its number in list
+  set-role <role> <name or n>
+                          Make a device the default for just one role (console, multimedia,
+                          communications), leaving the other two alone
+  mic-toggle              Switch to the next microphone in \"mics\"
+  profile <name>          Make a profile's pair the active one and switch to its speakers
+  list                    Show all output devices and their IDs
+  status                  Print the current device as JSON
+  current                 Print just the current device's name
+  suggest                 Print a config for the detected devices
+  diag                    Write a diagnostics file to the Desktop
+  export <path>           Copy the config to a file (TOML if it ends in .toml)
+  import <path>           Replace the config with a file's, backing up the current one
+  reset [--yes]           Back up and delete the config
+  uninstall [--yes]       Remove the config, logs, Start with Windows shortcut, and registry key
+  --enable-autostart      Start with Windows
+  --disable-autostart     Don't start with Windows
+  --help, --version       Show this help or the version
+
+Exit codes: 0 = success, 1 = failure, 2 = usage error.";
+
 fn main() {
-    // Initialize COM
-    unsafe {
-        CoInitializeEx(None, COINIT_APARTMENTTHREADED)
-            .ok()
-            .expect("Failed to initialize COM");
+    install_panic_hook();
+
+    // --help / --version don't need COM or the config
+    if let Some(flag) = std::env::args().skip(1).find(|a| matches!(a.as_str(), "--help" | "-h" | "--version" | "-V")) {
+        unsafe { let _ = AllocConsole(); }
+        if flag == "--version" || flag == "-V" {
+            println!("audio-output-switcher {}", env!("CARGO_PKG_VERSION"));
+        } else {
+            println!("{}", HELP);
+        }
+        pause();
+        unsafe { let _ = FreeConsole(); }
+        return;
+    }
+
+    if let Err(e) = init_com() {
+        show_error(&format!("Couldn't initialize COM, which the audio device APIs need: {}", e));
+        std::process::exit(1);
+    }
+
+    // --portable can accompany any mode, so strip it before CLI dispatch
+    let mut args: Vec<String> = std::env::args().collect();
+    if let Some(pos) = args.iter().skip(1).position(|a| a == "--portable") {
+        args.remove(pos + 1);
+        config::set_portable();
+    }
+    // --config <path> likewise; it overrides AUDIO_SWITCHER_CONFIG
+    if let Some(pos) = args.iter().skip(1).position(|a| a == "--config") {
+        if pos + 2 >= args.len() {
+            eprintln!("--config needs a file path");
+            std::process::exit(2);
+        }
+        let path = args.remove(pos + 2);
+        args.remove(pos + 1);
+        config::set_config_override(path.into());
+    }
+
+    // --no-tray only matters without a command, but is accepted (and ignored) alongside one
+    let no_tray = match args.iter().skip(1).position(|a| a == "--no-tray") {
+        Some(pos) => {
+            args.remove(pos + 1);
+            true
+        }
+        None => false,
+    };
+
+    // --quiet likewise, so `speakers --quiet` and `--quiet speakers` both work. It's for one
+    // command, so it mustn't silence a whole tray session started without one.
+    if let Some(pos) = args.iter().skip(1).position(|a| a == "--quiet") {
+        args.remove(pos + 1);
+        if args.len() <= 1 {
+            eprintln!("--quiet needs a command; to silence the tray, turn off Notification Sound in its menu");
+            std::process::exit(2);
+        }
+        QUIET.store(true, Ordering::Release);
     }
 
-    // CLI mode: audio-output-switcher.exe [speakers|headphones|toggle]
-    let args: Vec<String> = std::env::args().collect();
+    // CLI mode: audio-output-switcher.exe [speakers|headphones|toggle|next|prev|device <n>|...]
     if args.len() > 1 {
-        run_cli(&args[1]);
-        return;
+        std::process::exit(run_cli(&args[1..]));
     }
 
-    // Load or create config (allocate a temporary console for first-time setup)
+    // Only one tray instance per config file; a second launch just exits
+    let Some(_instance) = acquire_instance_mutex() else {
+        return;
+    };
+
+    // Load or create config (first-time setup)
     let mut cfg = match config::load() {
-        Some(cfg) => cfg,
-        None => {
-            unsafe { let _ = AllocConsole(); }
-            println!("No config found. Running first-time setup...\n");
-            let result = run_setup();
-            unsafe { let _ = FreeConsole(); }
-            match result {
+        Ok(cfg) => cfg,
+        Err(error::Error::ConfigNotFound(_)) => match run_setup(None) {
+            Some(cfg) => cfg,
+            None => return,
+        },
+        // Never replace a config the user wrote just because of a typo; ask first
+        Err(e) => {
+            if !confirm_dialog(&format!(
+                "Couldn't load the config:\n\n{}\n\nFix the file and start again, or choose Yes to \
+                 move it aside and run setup.",
+                e
+            )) {
+                return;
+            }
+            match config::reset() {
+                Ok(backup) => log::info(&format!("Moved invalid config to {}", backup.display())),
+                Err(e) => {
+                    show_error(&format!("Couldn't move the config aside: {}", e));
+                    return;
+                }
+            }
+            match run_setup(None) {
                 Some(cfg) => cfg,
                 None => return,
             }
         }
     };
 
+    log::set_level(cfg.log_level);
+    log::set_format(cfg.log_format);
+    log::info(&format!("Starting Audio Output Switcher {}", env!("CARGO_PKG_VERSION")));
+    log_devices();
+    resolve_moved_devices(&mut cfg);
+    apply_startup_device(&cfg);
+
     // Determine initial state (which device is currently default)
-    let is_speakers = is_current_speakers(&cfg);
+    let slot = current_slot(&cfg);
 
-    // Register toggle hotkey
-    if hotkey::register(&cfg.hotkey).is_err() {
+    // Register hotkeys
+    register_hotkeys(&cfg);
+
+    // Set up tray with initial state
+    apply_settings(&cfg);
+    let headless = no_tray || cfg.headless;
+    if headless {
+        log::info("Running without a tray icon");
+    }
+    if let Err(e) = tray::setup(slot, !headless) {
+        show_error(&format!("Could not create the tray icon's message window: {}", e));
+        hotkey::unregister();
         return;
     }
 
-    // Set up tray with initial state
-    tray::set_notify_sound(cfg.notify_sound);
-    tray::setup(is_speakers);
+    if cfg.enable_pipe {
+        pipe::start();
+    }
+
+    // Pick up hand edits to the config without a restart
+    config::watch(|| tray::post_to_main(tray::WM_APP_RELOAD));
+
+    // Track default device changes (from any source) for the "mru" toggle mode
+    if let Ok(id) = audio::get_default_device_id(audio::Direction::Render) {
+        record_default(id);
+    }
+    if let Err(e) = audio::watch_devices(on_device_event) {
+        eprintln!("Failed to register for device notifications: {}", e);
+    }
+
+    // Power source notifications (always registered so enabling power_switch needs no restart)
+    power::register(tray::msg_hwnd());
+    let mut last_on_battery = power::is_on_battery();
+    if cfg.power_switch
+        && let Some(on_battery) = last_on_battery
+    {
+        apply_power_device(&cfg, on_battery, false);
+    }
+
+    // When the toggle/previous hotkey or a tray click last switched, for debounce_ms (and, with
+    // repeat_hotkey, for auto-repeat, which fires far faster than a switch should happen)
+    let mut last_cycle: Option<Instant> = None;
 
     // Message loop
     loop {
@@ -75,15 +283,102 @@ fn main() {
             while GetMessageW(&mut msg, None, 0, 0).as_bool() {
                 match msg.message {
                     WM_HOTKEY => match msg.wParam.0 as i32 {
-                        HOTKEY_TOGGLE => toggle_device(&cfg),
+                        id @ (hotkey::HOTKEY_TOGGLE | hotkey::HOTKEY_PREV) => {
+                            if in_cooldown(last_cycle, Instant::now(), toggle_cooldown(&cfg)) {
+                                continue;
+                            }
+                            last_cycle = Some(Instant::now());
+                            if id == hotkey::HOTKEY_TOGGLE {
+                                toggle_device(&cfg, log::Trigger::Hotkey);
+                            } else {
+                                cycle_device(&cfg, -1, log::Trigger::Hotkey);
+                            }
+                        }
+                        hotkey::HOTKEY_SOUND_SETTINGS => tray::open_sound_settings(),
+                        hotkey::HOTKEY_MUTE => toggle_mute(&cfg),
+                        hotkey::HOTKEY_PEEK => peek_device(&cfg),
+                        hotkey::HOTKEY_MIC_TOGGLE => {
+                            if let Some(name) = toggle_mic(&cfg) {
+                                tray::show_balloon("Microphone", &name);
+                                play_switch_sound(SwitchSound::Switched(config::Slot::Other), false);
+                            }
+                        }
+                        id if id >= hotkey::HOTKEY_DEVICE_BASE => {
+                            if let Some(binding) = cfg.hotkeys.get((id - hotkey::HOTKEY_DEVICE_BASE) as usize) {
+                                switch_to_id(&cfg, &binding.device, log::Trigger::Hotkey);
+                            }
+                        }
+                        id if id >= hotkey::HOTKEY_PROFILE_BASE => {
+                            activate_profile(&mut cfg, (id - hotkey::HOTKEY_PROFILE_BASE) as usize, log::Trigger::Hotkey);
+                        }
                         _ => {}
                     },
-                    tray::WM_APP_TOGGLE => toggle_device(&cfg),
+                    tray::WM_APP_TOGGLE => {
+                        if !in_cooldown(last_cycle, Instant::now(), toggle_cooldown(&cfg)) {
+                            last_cycle = Some(Instant::now());
+                            toggle_device(&cfg, log::Trigger::Tray);
+                        }
+                    }
                     tray::WM_APP_TOGGLE_SOUND => {
                         cfg.notify_sound = !cfg.notify_sound;
                         tray::set_notify_sound(cfg.notify_sound);
-                        config::save(&cfg);
+                        sync_last_device(&mut cfg);
+                        if let Err(e) = config::save(&cfg) {
+                            eprintln!("Failed to save config: {}", e);
+                        }
                     }
+                    tray::WM_APP_UNDO => {
+                        if let Some(device_id) = toast::take_pending_undo() {
+                            undo_switch(&cfg, &device_id);
+                        }
+                    }
+                    tray::WM_APP_SWITCH_TO => {
+                        if let Some(device) = tray::menu_device(msg.wParam.0) {
+                            switch_to_device(&cfg, &device, log::Trigger::Tray);
+                        }
+                    }
+                    tray::WM_APP_POWER_SOURCE => {
+                        let on_battery = msg.wParam.0 != 0;
+                        // Windows also sends the current state right after registering; only act on transitions
+                        if last_on_battery != Some(on_battery) {
+                            last_on_battery = Some(on_battery);
+                            if cfg.power_switch {
+                                apply_power_device(&cfg, on_battery, true);
+                            }
+                        }
+                    }
+                    tray::WM_APP_PROFILE => activate_profile(&mut cfg, msg.wParam.0, log::Trigger::Tray),
+                    tray::WM_APP_PIPE_COMMAND => {
+                        if let Some((command, reply)) = pipe::take_command() {
+                            let _ = reply.send(run_pipe_command(&cfg, command));
+                        }
+                    }
+                    tray::WM_APP_DEFAULT_CHANGED => {
+                        DEFAULT_CHANGE_QUEUED.store(false, Ordering::Release);
+                        tray::update_state(current_slot(&cfg));
+                    }
+                    tray::WM_APP_FOREGROUND => {
+                        if let Some(app) = foreground::take_app() {
+                            apply_app_device(&cfg, &app);
+                        }
+                    }
+                    tray::WM_APP_DEVICE_ARRIVED => {
+                        let arrived = std::mem::take(&mut *ARRIVED_DEVICES.lock().unwrap());
+                        for id in arrived {
+                            on_device_arrived(&cfg, &id);
+                        }
+                    }
+                    tray::WM_APP_RELOAD => match config::load() {
+                        Ok(new_cfg) => {
+                            cfg = new_cfg;
+                            hotkey::unregister();
+                            register_hotkeys(&cfg);
+                            apply_settings(&cfg);
+                            tray::update_state(current_slot(&cfg));
+                        }
+                        // Probably a half-written save; the next write triggers another reload
+                        Err(e) => log::error(&format!("Config changed but can't be loaded ({}); keeping the current settings", e)),
+                    },
                     tray::WM_APP_RECONFIGURE => {
                         RECONFIGURE.store(true, Ordering::Release);
                         break;
@@ -104,224 +399,1751 @@ fn main() {
         RECONFIGURE.store(false, Ordering::Release);
         hotkey::unregister();
 
-        unsafe { let _ = AllocConsole(); }
-        println!("\n--- Reconfigure ---\n");
-        let result = run_setup();
-        unsafe { let _ = FreeConsole(); }
+        sync_last_device(&mut cfg);
+        let result = run_setup(Some(&cfg));
 
         match result {
             Some(new_cfg) => {
                 cfg = new_cfg;
-                let is_spk = is_current_speakers(&cfg);
-                if hotkey::register(&cfg.hotkey).is_err() {
-                    break;
-                }
-                tray::set_notify_sound(cfg.notify_sound);
-                tray::update_state(is_spk);
-            }
-            None => {
-                break;
+                let slot = current_slot(&cfg);
+                register_hotkeys(&cfg);
+                apply_settings(&cfg);
+                tray::update_state(slot);
             }
+            // Cancelled: carry on with the old settings
+            None => register_hotkeys(&cfg),
         }
     }
 
+    power::unregister();
+    foreground::set_enabled(false);
+    audio::restore_ducked_sessions();
+    audio::unwatch_devices();
     tray::cleanup();
     hotkey::unregister();
 }
 
-fn run_cli(command: &str) {
-    let cfg = match config::load() {
-        Some(cfg) => cfg,
-        None => {
-            eprintln!("No config found. Run without arguments to set up.");
-            return;
-        }
-    };
+/// Minimum gap between two toggles from the hotkeys or the tray: `debounce_ms`, stretched to
+/// the hold interval when `repeat_hotkey` is on.
+fn toggle_cooldown(cfg: &config::Config) -> Duration {
+    let debounce = Duration::from_millis(cfg.debounce_ms);
+    if cfg.repeat_hotkey { debounce.max(HOLD_CYCLE_INTERVAL) } else { debounce }
+}
 
-    let target = match command.to_lowercase().as_str() {
-        "speakers" => Some((&cfg.speakers, true)),
-        "headphones" => Some((&cfg.headphones, false)),
-        "toggle" => {
-            let is_spk = is_current_speakers(&cfg);
-            if is_spk {
-                Some((&cfg.headphones, false))
-            } else {
-                Some((&cfg.speakers, true))
-            }
-        }
-        _ => {
-            eprintln!("Usage: audio-output-switcher.exe [speakers|headphones|toggle]");
-            None
-        }
-    };
+/// Whether a toggle at `now` falls within `cooldown` of the last one and should be ignored
+/// (a keyboard double-firing the hotkey, or a double-click on the tray icon).
+fn in_cooldown(last: Option<Instant>, now: Instant, cooldown: Duration) -> bool {
+    last.is_some_and(|t| now.saturating_duration_since(t) < cooldown)
+}
 
-    if let Some((device_id, is_speakers)) = target {
-        if let Err(e) = audio::set_default_device(device_id) {
-            eprintln!("Failed to switch: {}", e);
-            return;
-        }
-        // Notify running tray instance and play sound (sync so process doesn't exit early)
-        notify_running_instance(is_speakers);
-        play_switch_sound(true);
+/// Show panics in a dialog (and the log) as well as on stderr: with the windows subsystem
+/// there's usually no console, so otherwise the app would just vanish.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        show_error(&format!(
+            "Audio Output Switcher ran into an unexpected error:\n\n{}\n\n\
+             Please include this message if you report the problem.",
+            info
+        ));
+        // Last, since finding the log file can itself be what panicked
+        log::error(&format!("Panic: {}", info));
+    }));
+}
+
+/// Initialize COM (single-threaded apartment) on this thread. COM that's already initialized,
+/// even with a different threading model (RPC_E_CHANGED_MODE), is fine to carry on with.
+fn init_com() -> windows::core::Result<()> {
+    let hr = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+    if hr == RPC_E_CHANGED_MODE {
+        return Ok(());
     }
+    hr.ok()
 }
 
-fn notify_running_instance(is_speakers: bool) {
-    let class_name: Vec<u16> = tray::MSG_WINDOW_CLASS
+/// Create the named mutex that marks a running tray instance for this config file. Returns
+/// None if another instance already holds it. The handle is kept open for the process lifetime.
+fn acquire_instance_mutex() -> Option<HANDLE> {
+    // Backslashes are only allowed in the namespace prefix, so key on a hash of the config path.
+    // The hash must be the same in every build, so instances of different versions still meet.
+    let path = config::config_path().to_string_lossy().to_lowercase();
+    let name: Vec<u16> = format!("Local\\AudioSwitcher-{:016x}", fnv1a(path.as_bytes()))
         .encode_utf16()
         .chain(std::iter::once(0))
         .collect();
+
     unsafe {
-        let hwnd = FindWindowW(PCWSTR(class_name.as_ptr()), PCWSTR::null());
-        if let Ok(hwnd) = hwnd {
-            SendMessageW(
-                hwnd,
-                tray::WM_APP_REFRESH_STATE,
-                Some(WPARAM(is_speakers as usize)),
-                Some(LPARAM(0)),
-            );
+        match CreateMutexW(None, true, PCWSTR(name.as_ptr())) {
+            Ok(_) if GetLastError() == ERROR_ALREADY_EXISTS => None,
+            Ok(handle) => Some(handle),
+            // Couldn't create it at all; don't let that stop the app from starting
+            Err(e) => {
+                eprintln!("Failed to create instance mutex: {}", e);
+                Some(HANDLE::default())
+            }
         }
     }
 }
 
-fn is_current_speakers(cfg: &config::Config) -> bool {
-    audio::get_default_device_id()
-        .map(|id| id == cfg.speakers)
-        .unwrap_or(true)
+/// 64-bit FNV-1a hash. Unlike std's DefaultHasher, its output is fixed across Rust releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
 }
 
-fn toggle_device(cfg: &config::Config) {
-    let current_id = match audio::get_default_device_id() {
-        Ok(id) => id,
-        Err(e) => {
-            eprintln!("Failed to get current device: {}", e);
-            return;
-        }
-    };
+/// Push the config's tray and sound settings to the modules that keep their own copy.
+fn apply_settings(cfg: &config::Config) {
+    log::set_level(cfg.log_level);
+    log::set_format(cfg.log_format);
+    set_switch_sound(cfg);
+    tray::set_use_system_icons(cfg.use_system_device_icons);
+    tray::set_state_role(cfg.state_role);
+    tray::set_custom_icons(
+        cfg.speakers_icon.as_ref().map(PathBuf::from),
+        cfg.headphones_icon.as_ref().map(PathBuf::from),
+    );
+    tray::set_show_split(cfg.tray_shows_split);
+    tray::set_show_mute_state(cfg.show_mute_state);
+    tray::set_show_notifications(cfg.show_notifications);
+    tray::set_click_action(cfg.click_action);
+    tray::set_double_click_action(cfg.double_click_action);
+    tray::set_device_ring(&cfg.device_ring());
+    tray::set_profiles(cfg.profiles.keys().cloned().collect(), cfg.active_profile.clone());
+    foreground::set_enabled(cfg.app_switch && !cfg.app_rules.is_empty());
+}
 
-    let (target_id, switching_to_speakers) = if current_id == cfg.speakers {
-        (&cfg.headphones, false)
-    } else {
-        (&cfg.speakers, true)
-    };
+/// Register the toggle hotkey and any optional ones. Failures are reported together in
+/// one dialog; the app keeps running so the tray icon and Reconfigure stay usable.
+fn register_hotkeys(cfg: &config::Config) {
+    hotkey::set_repeat_cycle(cfg.repeat_hotkey);
+    let mut hotkeys: Vec<(i32, String, &str)> = vec![(hotkey::HOTKEY_TOGGLE, "Toggle".to_string(), &cfg.hotkey)];
+    if let Some(prev) = &cfg.prev_hotkey {
+        hotkeys.push((hotkey::HOTKEY_PREV, "Previous device".to_string(), prev));
+    }
+    if let Some(mic) = &cfg.mic_hotkey {
+        hotkeys.push((hotkey::HOTKEY_MIC_TOGGLE, "Microphone toggle".to_string(), mic));
+    }
+    if let Some(settings) = &cfg.sound_settings_hotkey {
+        hotkeys.push((hotkey::HOTKEY_SOUND_SETTINGS, "Sound settings".to_string(), settings));
+    }
+    if let Some(mute) = &cfg.mute_hotkey {
+        hotkeys.push((hotkey::HOTKEY_MUTE, "Mute".to_string(), mute));
+    }
+    if let Some(peek) = &cfg.peek_hotkey {
+        hotkeys.push((hotkey::HOTKEY_PEEK, "Show current device".to_string(), peek));
+    }
+    for (i, (name, profile)) in cfg.profiles.iter().enumerate() {
+        if let Some(profile_hotkey) = &profile.hotkey {
+            hotkeys.push((hotkey::HOTKEY_PROFILE_BASE + i as i32, format!("Profile '{}'", name), profile_hotkey));
+        }
+    }
+    for (i, binding) in cfg.hotkeys.iter().enumerate() {
+        let label = audio::get_device_name(&binding.device).unwrap_or_else(|| binding.device.clone());
+        hotkeys.push((hotkey::HOTKEY_DEVICE_BASE + i as i32, format!("Switch to '{}'", label), &binding.hotkey));
+    }
 
-    match audio::set_default_device(target_id) {
-        Ok(()) => {
-            tray::update_state(switching_to_speakers);
-            play_switch_sound(false);
+    let pairs: Vec<(i32, &str)> = hotkeys.iter().map(|&(id, _, hotkey_str)| (id, hotkey_str)).collect();
+    let failed = hotkey::register_all(&pairs);
+    for (id, label, hotkey_str) in &hotkeys {
+        if !failed.iter().any(|f| f.0 == *id) {
+            log::debug(&format!("Registered hotkey {} ({})", hotkey_str, label));
         }
-        Err(e) => eprintln!("Failed to switch device: {}", e),
+    }
+    let failures: Vec<String> = failed
+        .into_iter()
+        .map(|(id, hotkey_str, e)| {
+            let label = hotkeys.iter().find(|o| o.0 == id).map_or("", |o| o.1.as_str());
+            format!("{} ({}): {}", label, hotkey_str, e)
+        })
+        .collect();
+    for failure in &failures {
+        log::error(&format!("Hotkey registration failed: {}", failure));
+    }
+    if !failures.is_empty() {
+        show_error(&format!(
+            "Some hotkeys could not be registered:\n\n{}\n\nRight-click the tray icon and choose \
+             Reconfigure to pick a different toggle hotkey.",
+            failures.join("\n")
+        ));
     }
 }
 
-fn play_switch_sound(sync: bool) {
-    if !tray::is_notify_sound() {
-        return;
+/// Run a CLI command and return the process exit code (0 = success, 1 = failure, 2 = usage).
+fn run_cli(args: &[String]) -> i32 {
+    let command = args[0].to_lowercase();
+
+    // Reset must work even when the config is unreadable, so handle it before loading
+    if command == "reset" {
+        let skip_confirm = args[1..].iter().any(|a| a == "--yes" || a == "-y");
+        run_reset(skip_confirm);
+        return 0;
     }
-    // Check for notify.wav next to the exe — use that if it exists, otherwise embedded default
-    if let Some(notify_path) = std::env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|d| d.join("notify.wav")))
-        .filter(|p| p.exists())
-    {
-        let path_wide: Vec<u16> = notify_path
-            .to_string_lossy()
-            .encode_utf16()
-            .chain(std::iter::once(0))
-            .collect();
-        let flags = if sync { SND_FILENAME } else { SND_FILENAME | SND_ASYNC };
-        unsafe {
-            let _ = PlaySoundW(PCWSTR(path_wide.as_ptr()), None, flags);
-        }
-    } else {
-        let flags = if sync { SND_MEMORY } else { SND_MEMORY | SND_ASYNC };
-        unsafe {
-            let _ = PlaySoundW(PCWSTR(SWITCH_SOUND.as_ptr() as *const u16), None, flags);
-        }
+
+    if command == "uninstall" {
+        let skip_confirm = args[1..].iter().any(|a| a == "--yes" || a == "-y");
+        return run_uninstall(skip_confirm);
     }
-}
 
-fn run_setup() -> Option<config::Config> {
-    let devices = audio::list_devices().expect("Failed to enumerate audio devices");
+    if command == "suggest" {
+        run_suggest();
+        return 0;
+    }
 
-    if devices.len() < 2 {
-        eprintln!(
-            "Need at least 2 audio output devices. Found {}.",
-            devices.len()
-        );
-        return None;
+    if command == "--enable-autostart" || command == "--disable-autostart" {
+        let enable = command == "--enable-autostart";
+        return match autostart::set_enabled(enable) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("Failed to change autostart: {}", e);
+                1
+            }
+        };
     }
 
-    println!("Available audio output devices:");
-    for (i, dev) in devices.iter().enumerate() {
-        println!("  [{}] {}", i + 1, dev.name);
+    if command == "diag" {
+        return run_diag();
     }
-    println!();
 
-    let a = prompt_device_choice("Select Speakers (number): ", devices.len())?;
-    let b = prompt_device_choice("Select Headphones (number): ", devices.len())?;
+    if command == "list" {
+        return run_list();
+    }
 
-    if a == b {
-        eprintln!("Speakers and Headphones must be different devices.");
-        return None;
+    if command == "set-role" {
+        return run_set_role(&args[1..]);
     }
 
-    let hotkey_str = prompt_hotkey()?;
+    // Import replaces the config, so it doesn't matter whether the current one loads
+    if command == "import" {
+        return run_import(&args[1..]);
+    }
 
-    let cfg = config::Config {
-        speakers: devices[a].id.clone(),
-        headphones: devices[b].id.clone(),
-        hotkey: hotkey_str,
-        notify_sound: true,
+    let mut cfg = match config::load() {
+        Ok(cfg) => cfg,
+        Err(error::Error::ConfigNotFound(_)) => {
+            eprintln!("No config found. Run without arguments to set up.");
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
     };
+    log::set_level(cfg.log_level);
+    log::set_format(cfg.log_format);
+    resolve_moved_devices(&mut cfg);
+    set_switch_sound(&cfg);
 
-    config::save(&cfg);
-    println!(
-        "\nConfig saved. Speakers = '{}', Headphones = '{}'",
-        devices[a].name, devices[b].name
-    );
-    println!("Hotkey: {}", cfg.hotkey);
+    if command == "status" {
+        return run_status(&cfg);
+    }
 
-    Some(cfg)
-}
+    if command == "current" {
+        return run_current(&cfg);
+    }
 
-fn prompt_hotkey() -> Option<String> {
-    loop {
-        print!("Enter hotkey (default: Ctrl+Alt+S): ");
-        io::stdout().flush().ok()?;
+    if command == "export" {
+        let Some(dest) = args.get(1) else {
+            eprintln!("Usage: audio-output-switcher.exe export <path>");
+            return 2;
+        };
+        return match config::export(&cfg, Path::new(dest)) {
+            Ok(()) => {
+                println!("Config exported to {}", dest);
+                0
+            }
+            Err(e) => {
+                eprintln!("Failed to export config: {}", e);
+                1
+            }
+        };
+    }
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).ok()?;
-        let input = input.trim();
+    // "profile" swaps the named pair in (saved, so a running tray reloads it) before switching
+    if command == "profile" {
+        let names = cfg.profiles.keys().cloned().collect::<Vec<_>>().join(", ");
+        let Some(name) = args.get(1) else {
+            eprintln!("Usage: audio-output-switcher.exe profile <name>  (profiles: {})", names);
+            return 2;
+        };
+        if !cfg.activate_profile(name) {
+            eprintln!("No profile named '{}'. Profiles: {}", name, names);
+            return 1;
+        }
+        if let Err(e) = config::save(&cfg) {
+            eprintln!("Failed to save config: {}", e);
+            return 1;
+        }
+    }
 
-        let hotkey_str = if input.is_empty() {
-            "Ctrl+Alt+S".to_string()
-        } else {
-            input.to_string()
+    // "set" names any active device, so its ID is looked up up front rather than borrowed from cfg
+    let set_target = if command == "set" {
+        let Some(query) = args.get(1) else {
+            eprintln!("Usage: audio-output-switcher.exe set <name or number from list>");
+            return 2;
         };
+        match find_device_arg(&audio::WindowsAudio, query) {
+            Ok(id) => id,
+            Err(msg) => {
+                eprintln!("{}", msg);
+                return 1;
+            }
+        }
+    } else {
+        String::new()
+    };
 
-        match hotkey::parse_hotkey(&hotkey_str) {
-            Ok(_) => return Some(hotkey_str),
-            Err(e) => {
-                eprintln!("Invalid hotkey '{}': {}", hotkey_str, e);
-                eprintln!("Format: Modifier+Modifier+Key (e.g. Ctrl+Alt+S, Ctrl+Shift+F1)");
+    let target_id = match command.as_str() {
+        "set" => set_target.as_str(),
+        "speakers" | "profile" => cfg.speakers.as_str(),
+        "headphones" => cfg.headphones.as_str(),
+        "toggle" => {
+            // --role picks which role's current default decides the direction
+            let role = match args.iter().position(|a| a == "--role") {
+                Some(i) => match args.get(i + 1).and_then(|name| audio::Role::parse(name)) {
+                    Some(role) => role,
+                    None => {
+                        eprintln!("Usage: audio-output-switcher.exe toggle [--role console|multimedia|communications]");
+                        return 2;
+                    }
+                },
+                None => audio::Role::Console,
+            };
+            // With no default at all, start from the first device
+            next_device(&audio::WindowsAudio, &cfg, role, 1).unwrap_or_else(|_| cfg.device_ring()[0])
+        }
+        "mic-toggle" => {
+            if cfg.mics.is_empty() {
+                eprintln!("No microphones configured. Add capture device IDs to \"mics\" in the config.");
+                return 2;
+            }
+            return match toggle_mic(&cfg) {
+                Some(_) => {
+                    play_switch_sound(SwitchSound::Switched(config::Slot::Other), true);
+                    0
+                }
+                None => 1,
+            };
+        }
+        "next" | "prev" => {
+            let step = if command == "next" { 1 } else { -1 };
+            match next_device(&audio::WindowsAudio, &cfg, audio::Role::Console, step) {
+                Ok(id) => id,
+                Err(e) => {
+                    eprintln!("Failed to get current device: {}", e);
+                    return 1;
+                }
+            }
+        }
+        "device" => {
+            let ring = cfg.device_ring();
+            match args.get(1).and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if (1..=ring.len()).contains(&n) => ring[n - 1],
+                _ => {
+                    eprintln!("Usage: audio-output-switcher.exe device <1-{}>", ring.len());
+                    return 2;
+                }
             }
         }
+        _ => {
+            eprintln!("Unknown command '{}'. Run with --help to see the commands.", command);
+            return 2;
+        }
+    };
+
+    // Commands naming a specific device honour repeat_action when it's already the default
+    let target_id = if matches!(command.as_str(), "speakers" | "headphones" | "device" | "set") {
+        match resolve_repeat(&cfg, target_id, true) {
+            Some(id) => id,
+            None => return 0,
+        }
+    } else {
+        target_id
+    };
+
+    if let Err(e) = set_default(&cfg, target_id, log::Trigger::Cli) {
+        eprintln!("Failed to switch: {}", e);
+        play_switch_sound(SwitchSound::Failed, true);
+        return 1;
     }
+    // Notify running tray instance and play sound (sync so process doesn't exit early)
+    notify_running_instance(cfg.slot(target_id));
+    play_switch_sound(SwitchSound::Switched(cfg.slot(target_id)), true);
+    0
 }
 
-fn prompt_device_choice(prompt: &str, max: usize) -> Option<usize> {
-    print!("{}", prompt);
-    io::stdout().flush().ok()?;
+/// Print a ready-to-use config for the detected devices to stdout (device list goes to stderr,
+/// so `suggest > config.json` produces valid JSON).
+fn run_suggest() {
+    let devices = match audio::list_devices(audio::Direction::Render) {
+        Ok(devices) => devices,
+        Err(e) => {
+            eprintln!("Failed to enumerate audio devices: {}", e);
+            return;
+        }
+    };
+    if devices.len() < 2 {
+        eprintln!("Need at least 2 audio output devices. Found {}.", devices.len());
+        return;
+    }
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).ok()?;
+    eprintln!("Detected output devices:");
+    for dev in &devices {
+        eprintln!("  {} [{}]", dev.name, dev.form_factor.label());
+    }
 
-    let n: usize = input.trim().parse().ok()?;
-    if n < 1 || n > max {
-        eprintln!("Invalid choice: {}", n);
-        return None;
+    let (spk, hp) = suggest_pair(&devices);
+    eprintln!("\nSuggested: Speakers = '{}', Headphones = '{}'\n", devices[spk].name, devices[hp].name);
+
+    let cfg = config::Config {
+        speakers: devices[spk].id.clone(),
+        headphones: devices[hp].id.clone(),
+        speakers_name: Some(devices[spk].name.clone()),
+        headphones_name: Some(devices[hp].name.clone()),
+        ..Default::default()
+    };
+    match serde_json::to_string_pretty(&cfg) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("Failed to serialize config: {}", e),
+    }
+}
+
+/// Pick likely (speakers, headphones) indices from form factors. Falls back to the first
+/// two devices when the drivers don't report anything useful.
+fn suggest_pair(devices: &[audio::AudioDevice]) -> (usize, usize) {
+    use audio::FormFactor;
+
+    let hp = devices
+        .iter()
+        .position(|d| matches!(d.form_factor, FormFactor::Headphones | FormFactor::Headset))
+        .unwrap_or(1);
+    let spk = devices
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != hp)
+        .find(|(_, d)| d.form_factor == FormFactor::Speakers)
+        .or_else(|| devices.iter().enumerate().find(|&(i, _)| i != hp))
+        .map_or(0, |(i, _)| i);
+    (spk, hp)
+}
+
+/// Back up and delete the config so the next launch runs first-time setup.
+fn run_reset(skip_confirm: bool) {
+    unsafe { let _ = AllocConsole(); }
+
+    let path = config::config_path();
+    if !path.exists() {
+        println!("No config found at {}. Nothing to reset.", path.display());
+    } else if skip_confirm || confirm(&format!("Delete config at {}? [y/N]: ", path.display())) {
+        match config::reset() {
+            Ok(backup) => {
+                println!("Config backed up to {}", backup.display());
+                println!("The next launch will run first-time setup.");
+            }
+            Err(e) => eprintln!("Failed to reset config: {}", e),
+        }
+    } else {
+        println!("Reset cancelled.");
+    }
+
+    if !skip_confirm {
+        pause();
     }
+    unsafe { let _ = FreeConsole(); }
+}
 
-    Some(n - 1)
+/// Delete the app's stored files, its Startup shortcut, and its toast registration, listing
+/// each thing removed.
+fn run_uninstall(skip_confirm: bool) -> i32 {
+    unsafe { let _ = AllocConsole(); }
+
+    let class_name: Vec<u16> = tray::MSG_WINDOW_CLASS.encode_utf16().chain(std::iter::once(0)).collect();
+    let running = unsafe { FindWindowW(PCWSTR(class_name.as_ptr()), PCWSTR::null()).is_ok() };
+    let paths = config::stored_paths();
+    let autostart = autostart::is_enabled();
+    let app_id = toast::is_app_id_registered();
+
+    let code = if running {
+        eprintln!("Audio Output Switcher is running. Exit it from the tray menu first.");
+        1
+    } else if paths.is_empty() && !autostart && !app_id {
+        println!("Nothing to remove.");
+        0
+    } else {
+        println!("This will remove:");
+        for path in &paths {
+            println!("  {}", path.display());
+        }
+        if autostart {
+            println!("  the Start with Windows shortcut");
+        }
+        if app_id {
+            println!("  the registry key HKEY_CURRENT_USER\\{}", toast::app_id_key());
+        }
+        if skip_confirm || confirm("Continue? [y/N]: ") {
+            let mut code = 0;
+            if autostart {
+                match autostart::set_enabled(false) {
+                    Ok(()) => println!("Removed the Start with Windows shortcut"),
+                    Err(e) => {
+                        eprintln!("Failed to remove the Start with Windows shortcut: {}", e);
+                        code = 1;
+                    }
+                }
+            }
+            if app_id {
+                match toast::unregister_app_id() {
+                    Ok(()) => println!("Removed the registry key for notifications"),
+                    Err(e) => {
+                        eprintln!("Failed to remove the registry key for notifications: {}", e);
+                        code = 1;
+                    }
+                }
+            }
+            for path in &paths {
+                let result = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+                match result {
+                    Ok(()) => println!("Removed {}", path.display()),
+                    Err(e) => {
+                        eprintln!("Failed to remove {}: {}", path.display(), e);
+                        code = 1;
+                    }
+                }
+            }
+            if code == 0 {
+                println!("Done. You can now delete the exe.");
+            }
+            code
+        } else {
+            println!("Uninstall cancelled.");
+            0
+        }
+    };
+
+    if !skip_confirm {
+        pause();
+    }
+    unsafe { let _ = FreeConsole(); }
+    code
+}
+
+/// Print every active output device with its endpoint ID; the current default is starred.
+fn run_list() -> i32 {
+    unsafe { let _ = AllocConsole(); }
+    let code = match audio::list_devices(audio::Direction::Render) {
+        Ok(devices) => {
+            let default_id = audio::get_default_device_id(audio::Direction::Render).unwrap_or_default();
+            for (i, dev) in devices.iter().enumerate() {
+                let marker = if dev.id == default_id { "*" } else { " " };
+                println!("{} {}. {}\n     {}", marker, i + 1, dev.name, dev.id);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to enumerate audio devices: {}", e);
+            1
+        }
+    };
+    pause();
+    unsafe { let _ = FreeConsole(); }
+    code
+}
+
+/// Replace the config with one from `import <path>`, after checking that it parses.
+fn run_import(args: &[String]) -> i32 {
+    let Some(src) = args.first() else {
+        eprintln!("Usage: audio-output-switcher.exe import <path>");
+        return 2;
+    };
+    match config::import(Path::new(src)) {
+        Ok(backup) => {
+            println!("Imported {} to {}", src, config::config_path().display());
+            if let Some(backup) = backup {
+                println!("The previous config was backed up to {}", backup.display());
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to import config: {}", e);
+            1
+        }
+    }
+}
+
+/// Make a device (by name or number, as for `set`) the default for one role only.
+fn run_set_role(args: &[String]) -> i32 {
+    let (Some(role), Some(query)) = (args.first().and_then(|name| audio::Role::parse(name)), args.get(1)) else {
+        eprintln!(
+            "Usage: audio-output-switcher.exe set-role console|multimedia|communications <name or number from list>"
+        );
+        return 2;
+    };
+    let device_id = match find_device_arg(&audio::WindowsAudio, query) {
+        Ok(id) => id,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return 1;
+        }
+    };
+    let name = audio::get_device_name(&device_id).unwrap_or_else(|| device_id.clone());
+    match audio::set_default_device_for_roles(&device_id, &[role]) {
+        Ok(()) => {
+            log::info(&format!("Set the {} default to {}", args[0].to_lowercase(), name));
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to switch: {}", e);
+            1
+        }
+    }
+}
+
+/// Resolve a `set` argument to a device ID: a number from `list`, or a case-insensitive piece
+/// of the device name (an exact name match wins over partial ones).
+fn find_device_arg(backend: &impl audio::AudioBackend, query: &str) -> std::result::Result<String, String> {
+    let devices = backend
+        .list_devices(audio::Direction::Render)
+        .map_err(|e| format!("Failed to enumerate audio devices: {}", e))?;
+
+    if let Ok(n) = query.parse::<usize>() {
+        return match devices.get(n.wrapping_sub(1)) {
+            Some(d) => Ok(d.id.clone()),
+            None => Err(format!("No device {}; `list` shows 1-{}", n, devices.len())),
+        };
+    }
+
+    let needle = query.to_lowercase();
+    if let Some(d) = devices.iter().find(|d| d.name.to_lowercase() == needle) {
+        return Ok(d.id.clone());
+    }
+    let matches: Vec<&audio::AudioDevice> =
+        devices.iter().filter(|d| d.name.to_lowercase().contains(&needle)).collect();
+    match matches.as_slice() {
+        [d] => Ok(d.id.clone()),
+        [] => Err(format!("No active output device matches '{}'", query)),
+        _ => Err(format!(
+            "'{}' matches several devices:\n  {}",
+            query,
+            matches.iter().map(|d| d.name.as_str()).collect::<Vec<_>>().join("\n  ")
+        )),
+    }
+}
+
+/// Print the current output device as one line of JSON. No console is allocated, so the
+/// output only goes somewhere when stdout is redirected (a pipe or file).
+fn run_status(cfg: &config::Config) -> i32 {
+    let id = match audio::get_default_device_id(audio::Direction::Render) {
+        Ok(id) => id,
+        Err(e) => {
+            eprintln!("Failed to get current device: {}", e);
+            return 1;
+        }
+    };
+    let slot = cfg.slot(&id);
+    let name = audio::get_device_name(&id);
+    let device = slot_label(slot, name.as_deref());
+    let status = serde_json::json!({
+        "device": device,
+        "name": name,
+        "id": id,
+        "is_speakers": slot == config::Slot::Speakers,
+    });
+    println!("{}", status);
+    0
+}
+
+/// Print the current output device's name on its own line, for shell prompts and status bars.
+/// Like `status`, it doesn't allocate a console, so it works in pipes.
+fn run_current(cfg: &config::Config) -> i32 {
+    match audio::get_default_device_id(audio::Direction::Render) {
+        Ok(id) => {
+            println!("{}", slot_label(cfg.slot(&id), audio::get_device_name(&id).as_deref()));
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to get current device: {}", e);
+            1
+        }
+    }
+}
+
+/// "Speakers" or "Headphones" for the configured pair, otherwise the device's own name.
+fn slot_label(slot: config::Slot, name: Option<&str>) -> String {
+    match slot {
+        config::Slot::Speakers => "Speakers".to_string(),
+        config::Slot::Headphones => "Headphones".to_string(),
+        config::Slot::Other => name.unwrap_or("Other").to_string(),
+    }
+}
+
+/// Write the diagnostics file and tell the user where it went.
+fn run_diag() -> i32 {
+    unsafe { let _ = AllocConsole(); }
+    let code = match diag::write_report() {
+        Ok(path) => {
+            println!("Diagnostics written to {}", path.display());
+            println!("Attach this file to your bug report.");
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to write diagnostics: {}", e);
+            1
+        }
+    };
+    pause();
+    unsafe { let _ = FreeConsole(); }
+    code
+}
+
+fn confirm(prompt: &str) -> bool {
+    print!("{}", prompt);
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return false;
+    }
+    matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Keep a freshly allocated console open until the user has read it.
+fn pause() {
+    print!("Press Enter to exit...");
+    let _ = io::stdout().flush();
+    let _ = io::stdin().read_line(&mut String::new());
+}
+
+fn notify_running_instance(slot: config::Slot) {
+    let class_name: Vec<u16> = tray::MSG_WINDOW_CLASS
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    unsafe {
+        let hwnd = FindWindowW(PCWSTR(class_name.as_ptr()), PCWSTR::null());
+        if let Ok(hwnd) = hwnd {
+            SendMessageW(
+                hwnd,
+                tray::WM_APP_REFRESH_STATE,
+                Some(WPARAM(tray::slot_to_wparam(slot))),
+                Some(LPARAM(1)),
+            );
+        }
+    }
+}
+
+fn current_slot(cfg: &config::Config) -> config::Slot {
+    audio::get_default_device_id_for_role(audio::Direction::Render, cfg.state_role)
+        .map_or(config::Slot::Speakers, |id| cfg.slot(&id))
+}
+
+/// Write the active output devices to the log (debug level).
+fn log_devices() {
+    match audio::list_devices(audio::Direction::Render) {
+        Ok(devices) => {
+            for d in devices {
+                log::debug(&format!("Output device: {} ({})", d.name, d.id));
+            }
+        }
+        Err(e) => log::error(&format!("Failed to enumerate audio devices: {}", e)),
+    }
+}
+
+/// Runs on the MMDevice notification thread.
+fn on_device_event(event: audio::DeviceEvent) {
+    match event {
+        audio::DeviceEvent::DefaultChanged(role, id) => {
+            // The MRU history follows the console default; any role may be the tray's state_role
+            if role == audio::Role::Console {
+                log::debug(&format!("Default output changed to {}", id));
+                record_default(id);
+            }
+            // Only post here: looking up the slot calls back into the audio APIs, which
+            // mustn't happen on the notification thread
+            if !DEFAULT_CHANGE_QUEUED.swap(true, Ordering::AcqRel) {
+                tray::post_to_main(tray::WM_APP_DEFAULT_CHANGED);
+            }
+        }
+        // Switching here would call back into the audio APIs; let the UI thread do it
+        audio::DeviceEvent::Arrived(id) => {
+            log::debug(&format!("Device arrived: {}", id));
+            ARRIVED_DEVICES.lock().unwrap().push(id);
+            tray::post_to_main(tray::WM_APP_DEVICE_ARRIVED);
+        }
+    }
+}
+
+/// Carry out a command from the named pipe. Returns the reply line: the current device's
+/// name afterwards, or `error: ...`.
+fn run_pipe_command(cfg: &config::Config, command: pipe::Command) -> String {
+    match command {
+        pipe::Command::Get => {}
+        pipe::Command::Toggle => toggle_device(cfg, log::Trigger::Pipe),
+        pipe::Command::Set(query) => match find_device_arg(&audio::WindowsAudio, &query) {
+            Ok(id) => switch_to_id(cfg, &id, log::Trigger::Pipe),
+            // Replies are single lines
+            Err(msg) => return format!("error: {}", msg.replace("\n  ", " | ")),
+        },
+    }
+    match audio::get_default_device_id(audio::Direction::Render) {
+        Ok(id) => audio::get_device_name(&id).unwrap_or(id),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+/// With auto_switch_on_connect on, switch to a configured device that just arrived, unless
+/// it's already the default.
+fn on_device_arrived(cfg: &config::Config, device_id: &str) {
+    if !cfg.auto_switch_on_connect || !cfg.device_ring().contains(&device_id) {
+        return;
+    }
+    if audio::get_default_device_id(audio::Direction::Render).is_ok_and(|id| id == device_id) {
+        return;
+    }
+    let name = audio::get_device_name(device_id).unwrap_or_else(|| device_id.to_string());
+    log::info(&format!("{} connected; switching to it", name));
+    switch_to_id(cfg, device_id, log::Trigger::Auto);
+}
+
+fn record_default(id: String) {
+    let mut history = DEFAULT_HISTORY.lock().unwrap();
+    if history.current.as_ref() != Some(&id) {
+        history.previous = history.current.replace(id);
+    }
+}
+
+fn toggle_device(cfg: &config::Config, trigger: log::Trigger) {
+    if cfg.toggle_mode == config::ToggleMode::Mru {
+        toggle_mru(cfg, trigger);
+        return;
+    }
+    cycle_device(cfg, 1, trigger);
+}
+
+/// Move `step` places through the configured devices (wrapping). If the current default
+/// isn't one of them, switch to the first.
+fn cycle_device(cfg: &config::Config, step: isize, trigger: log::Trigger) {
+    // Read before switching: the toast's Undo goes back to it
+    let current_id = audio::get_default_device_id(audio::Direction::Render).unwrap_or_default();
+    let target_id = match next_device(&audio::WindowsAudio, cfg, audio::Role::Console, step) {
+        Ok(id) => id,
+        Err(e) => {
+            eprintln!("Failed to get current device: {}", e);
+            return;
+        }
+    };
+
+    match set_default(cfg, target_id, trigger) {
+        Ok(()) => {
+            tray::update_state(cfg.slot(target_id));
+            play_switch_sound(SwitchSound::Switched(cfg.slot(target_id)), false);
+            if cfg.undo_toast {
+                let label = match cfg.slot(target_id) {
+                    config::Slot::Speakers => "Speakers".to_string(),
+                    config::Slot::Headphones => "Headphones".to_string(),
+                    config::Slot::Other => {
+                        audio::get_device_name(target_id).unwrap_or_else(|| "next device".to_string())
+                    }
+                };
+                if let Err(e) = toast::show_switch_toast(&label, &current_id) {
+                    eprintln!("Failed to show toast: {}", e);
+                }
+            } else {
+                tray::notify_switch(cfg.slot(target_id));
+            }
+        }
+        Err(e) => report_switch_error(&e),
+    }
+}
+
+/// Device `step` places from the current default for `role` through the configured devices
+/// (wrapping); the first one if the current default isn't configured.
+fn next_device<'a>(
+    backend: &impl audio::AudioBackend,
+    cfg: &'a config::Config,
+    role: audio::Role,
+    step: isize,
+) -> error::Result<&'a str> {
+    let current_id = backend.get_default_device_id(audio::Direction::Render, role)?;
+    Ok(cfg.cycle_target(&current_id, step))
+}
+
+/// Switch straight to `target_id` (a per-device hotkey), honouring `repeat_action`.
+fn switch_to_id(cfg: &config::Config, target_id: &str, trigger: log::Trigger) {
+    let Some(target_id) = resolve_repeat(cfg, target_id, false) else {
+        return;
+    };
+    match set_default(cfg, target_id, trigger) {
+        Ok(()) => {
+            tray::update_state(cfg.slot(target_id));
+            tray::notify_switch(cfg.slot(target_id));
+            play_switch_sound(SwitchSound::Switched(cfg.slot(target_id)), false);
+        }
+        Err(e) => report_switch_error(&e),
+    }
+}
+
+/// Tell the user why a switch failed, with the failure sound (the tray icon is left as it was).
+fn report_switch_error(e: &error::Error) {
+    eprintln!("Failed to switch device: {}", e);
+    play_switch_sound(SwitchSound::Failed, false);
+    if matches!(e, error::Error::DeviceNotFound(_) | error::Error::DeviceUnavailable(_)) {
+        tray::show_balloon("Device unavailable", "The device is unplugged or disabled.");
+    } else {
+        tray::show_balloon("Couldn't switch device", &e.to_string());
+    }
+}
+
+/// Make `target_id` the default, then duck background apps if it's the headphones and
+/// `duck_on_headphones` is set.
+fn set_default(cfg: &config::Config, target_id: &str, trigger: log::Trigger) -> error::Result<()> {
+    // Session volumes are remembered per endpoint, so restore ducked apps before leaving it
+    audio::restore_ducked_sessions();
+    let from_id = audio::get_default_device_id(audio::Direction::Render).unwrap_or_default();
+    let result = set_default_with_retries(&audio::WindowsAudio, cfg, target_id);
+    let describe = |id: &str| audio::get_device_name(id).unwrap_or_else(|| id.to_string());
+    match &result {
+        Ok(()) => {
+            log::switch(&describe(&from_id), &describe(target_id), trigger, None);
+            remember_last_device(cfg, target_id);
+            if let Some(&percent) = cfg.volumes.get(target_id)
+                && let Err(e) = audio::set_volume(target_id, percent as f32 / 100.0)
+            {
+                log::error(&format!("Failed to set volume on {}: {}", describe(target_id), e));
+            }
+        }
+        Err(e) => log::switch(&describe(&from_id), &describe(target_id), trigger, Some(&e.to_string())),
+    }
+
+    // Only the tray process lives long enough to restore ducked volumes later
+    if result.is_ok()
+        && cfg.duck_on_headphones
+        && target_id == cfg.headphones
+        && !tray::msg_hwnd().0.is_null()
+        && let Err(e) = audio::duck_background_sessions(target_id, cfg.duck_level.min(100) as f32 / 100.0)
+    {
+        eprintln!("Failed to duck background apps: {}", e);
+    }
+    result
+}
+
+/// Save `target_id` as the config's `last_device` when `restore_on_start` is on.
+fn remember_last_device(cfg: &config::Config, target_id: &str) {
+    if !cfg.restore_on_start {
+        return;
+    }
+    let mut last = LAST_DEVICE.lock().unwrap();
+    if last.as_deref().or(cfg.last_device.as_deref()) == Some(target_id) {
+        return;
+    }
+    *last = Some(target_id.to_string());
+
+    // Only last_device changes, so save a copy rather than requiring `&mut` everywhere
+    let mut saved = cfg.clone();
+    saved.last_device = last.clone();
+    if let Err(e) = config::save(&saved) {
+        eprintln!("Failed to save last device: {}", e);
+    }
+}
+
+/// Bring `cfg.last_device` up to date before saving `cfg` itself.
+fn sync_last_device(cfg: &mut config::Config) {
+    if let Some(id) = LAST_DEVICE.lock().unwrap().clone() {
+        cfg.last_device = Some(id);
+    }
+}
+
+// With verify_switch, how many times a switch that reported success but didn't stick is redone
+const VERIFY_RETRIES: u32 = 3;
+const VERIFY_DELAY: Duration = Duration::from_millis(100);
+
+/// Switch, muting across it when `mute_during_switch` is set. Failed attempts are retried
+/// `switch_retries` times, doubling the delay each time; a missing device fails straight away.
+/// With `verify_switch`, a "successful" switch that Windows didn't apply is redone too.
+fn set_default_with_retries(
+    backend: &impl audio::AudioBackend,
+    cfg: &config::Config,
+    target_id: &str,
+) -> error::Result<()> {
+    let mut delay = Duration::from_millis(cfg.switch_retry_delay_ms);
+    let mut attempt = 0;
+    let mut verify_attempt = 0;
+    loop {
+        let result = if cfg.mute_during_switch
+            && let Ok(current_id) = backend.get_default_device_id(audio::Direction::Render, audio::Role::Console)
+        {
+            backend.set_default_device_muted(&current_id, target_id, cfg.roles_for(target_id))
+        } else {
+            backend.set_default_device(target_id, cfg.roles_for(target_id))
+        };
+        match result {
+            Ok(()) if cfg.verify_switch && !switch_applied(backend, cfg, target_id) => {
+                if verify_attempt == VERIFY_RETRIES {
+                    return Err(error::Error::SwitchNotApplied(target_id.to_string()));
+                }
+                verify_attempt += 1;
+                log::info(&format!("Switch reported success but didn't take effect; retry {}", verify_attempt));
+                std::thread::sleep(VERIFY_DELAY);
+            }
+            Err(error::Error::Com(e) | error::Error::PolicyConfig(e)) if attempt < cfg.switch_retries => {
+                eprintln!("Switch attempt {} failed ({}); retrying", attempt + 1, e);
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether every role the switch was meant to set now reports `target_id` as its default.
+fn switch_applied(backend: &impl audio::AudioBackend, cfg: &config::Config, target_id: &str) -> bool {
+    cfg.roles_for(target_id)
+        .iter()
+        .all(|&role| backend.get_default_device_id(audio::Direction::Render, role).is_ok_and(|id| id == target_id))
+}
+
+/// Swap back to whichever device was default before the current one.
+fn toggle_mru(cfg: &config::Config, trigger: log::Trigger) {
+    let previous = DEFAULT_HISTORY.lock().unwrap().previous.clone();
+    let Some(target_id) = previous else {
+        tray::show_balloon("Audio Output Switcher", "No previous device to switch back to yet.");
+        return;
+    };
+
+    match set_default(cfg, &target_id, trigger) {
+        Ok(()) => {
+            tray::update_state(cfg.slot(&target_id));
+            tray::notify_switch(cfg.slot(&target_id));
+            play_switch_sound(SwitchSound::Switched(cfg.slot(&target_id)), false);
+        }
+        Err(e) => report_switch_error(&e),
+    }
+}
+
+/// Revert to the device that was active before the last switch (toast Undo button).
+fn undo_switch(cfg: &config::Config, device_id: &str) {
+    match set_default(cfg, device_id, log::Trigger::Tray) {
+        Ok(()) => {
+            tray::update_state(cfg.slot(device_id));
+            play_switch_sound(SwitchSound::Switched(cfg.slot(device_id)), false);
+        }
+        Err(e) => eprintln!("Failed to undo switch: {}", e),
+    }
+}
+
+/// Point configured device IDs that no longer exist at the endpoint that now has the same
+/// container ID (a USB device moved to another port) or, for speakers/headphones, the same
+/// friendly name. Also records containers and names for devices that are present. Saves the
+/// config if anything changed.
+fn resolve_moved_devices(cfg: &mut config::Config) {
+    let Ok(devices) = audio::list_devices_with_disabled() else {
+        return;
+    };
+
+    let containers = &mut cfg.device_containers;
+    let configured_ids = [&mut cfg.speakers, &mut cfg.headphones]
+        .into_iter()
+        .chain(cfg.ac_device.as_mut())
+        .chain(cfg.battery_device.as_mut())
+        .chain(cfg.startup_device.as_mut())
+        .chain(cfg.devices.iter_mut());
+
+    // Old -> new ID, for fields that repeat an ID already remapped (e.g. ac_device = speakers)
+    let mut moved: Vec<(String, String)> = Vec::new();
+    let mut changed = false;
+    for id in configured_ids {
+        if let Some((_, new_id)) = moved.iter().find(|(old_id, _)| old_id == id) {
+            *id = new_id.clone();
+            continue;
+        }
+        if let Some(device) = devices.iter().find(|d| d.id == *id) {
+            if let Some(container) = &device.container_id
+                && containers.get(container) != Some(id)
+            {
+                containers.insert(container.clone(), id.clone());
+                changed = true;
+            }
+            continue;
+        }
+
+        let Some(container) = containers
+            .iter()
+            .find(|(_, last_id)| *last_id == id)
+            .map(|(container, _)| container.clone())
+        else {
+            continue;
+        };
+        let mut matches = devices
+            .iter()
+            .filter(|d| d.container_id.as_ref() == Some(&container));
+        if let (Some(device), None) = (matches.next(), matches.next()) {
+            eprintln!("Device {} moved; now {}", id, device.id);
+            moved.push((id.clone(), device.id.clone()));
+            *id = device.id.clone();
+            containers.insert(container, device.id.clone());
+            changed = true;
+        }
+    }
+
+    // Last resort for the main pair (e.g. after a driver reinstall): the name stored at setup.
+    // A device whose ID still matches always wins, so duplicate names only matter here.
+    for (id, name) in [
+        (&mut cfg.speakers, &mut cfg.speakers_name),
+        (&mut cfg.headphones, &mut cfg.headphones_name),
+    ] {
+        match devices.iter().find(|d| d.id == *id) {
+            Some(device) => {
+                if name.is_none() {
+                    *name = Some(device.name.clone());
+                    changed = true;
+                }
+            }
+            None => {
+                if let Some(new_id) = name.as_deref().and_then(audio::find_device_by_name) {
+                    eprintln!("Device {} not found; using '{}' ({})", id, name.as_deref().unwrap_or_default(), new_id);
+                    *id = new_id;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    if changed && let Err(e) = config::save(cfg) {
+        eprintln!("Failed to save updated device IDs: {}", e);
+    }
+}
+
+/// Make `startup_device` (or `last_device` with `restore_on_start`) the default, silently.
+/// Leaves the current default alone if that device isn't connected.
+fn apply_startup_device(cfg: &config::Config) {
+    let restore = cfg.last_device.as_ref().filter(|_| cfg.restore_on_start);
+    let Some(device_id) = cfg.startup_device.as_ref().or(restore) else {
+        return;
+    };
+    if audio::get_default_device_id(audio::Direction::Render).is_ok_and(|id| &id == device_id) {
+        return;
+    }
+    let present = audio::list_devices(audio::Direction::Render).is_ok_and(|devices| devices.iter().any(|d| &d.id == device_id));
+    if !present {
+        eprintln!("Startup device {} is not available; keeping current default", device_id);
+        return;
+    }
+    if let Err(e) = set_default(cfg, device_id, log::Trigger::Auto) {
+        eprintln!("Failed to apply startup device: {}", e);
+    }
+}
+
+/// Switch to the device configured for the current power source, if it isn't already default.
+fn apply_power_device(cfg: &config::Config, on_battery: bool, sound: bool) {
+    let target_id = cfg.power_device(on_battery);
+    if audio::get_default_device_id(audio::Direction::Render).is_ok_and(|id| id == target_id) {
+        return;
+    }
+    match set_default(cfg, target_id, log::Trigger::Auto) {
+        Ok(()) => {
+            tray::update_state(cfg.slot(target_id));
+            if sound {
+                play_switch_sound(SwitchSound::Switched(cfg.slot(target_id)), false);
+            }
+        }
+        Err(e) => eprintln!("Failed to switch device for power source: {}", e),
+    }
+}
+
+/// With app_switch on, switch to the device `app_rules` gives for `app` (now in the
+/// foreground), unless it's already the default. Apps without a rule leave the device alone.
+fn apply_app_device(cfg: &config::Config, app: &str) {
+    if !cfg.app_switch {
+        return;
+    }
+    let Some(target_id) = cfg.app_device(app) else {
+        return;
+    };
+    if audio::get_default_device_id(audio::Direction::Render).is_ok_and(|id| id == target_id) {
+        return;
+    }
+    log::debug(&format!("{} is in the foreground", app));
+    match set_default(cfg, target_id, log::Trigger::Auto) {
+        Ok(()) => tray::update_state(cfg.slot(target_id)),
+        Err(e) => log::error(&format!("Failed to switch device for {}: {}", app, e)),
+    }
+}
+
+/// Make the next microphone in `mics` the default for all roles. Returns its name (or ID) on
+/// success, None if no mics are configured or the switch failed.
+fn toggle_mic(cfg: &config::Config) -> Option<String> {
+    let current_id = audio::get_default_device_id(audio::Direction::Capture).unwrap_or_default();
+    let target_id = cfg.mic_target(&current_id)?;
+    match audio::set_default_device(target_id) {
+        Ok(()) => Some(audio::get_device_name(target_id).unwrap_or_else(|| target_id.to_string())),
+        Err(e) => {
+            eprintln!("Failed to switch microphone: {}", e);
+            None
+        }
+    }
+}
+
+/// Mute or unmute the current output and show the new state in the tray tooltip.
+fn toggle_mute(cfg: &config::Config) {
+    match audio::toggle_mute() {
+        Ok(muted) => {
+            log::info(if muted { "Muted output" } else { "Unmuted output" });
+            tray::update_state(current_slot(cfg));
+        }
+        Err(e) => {
+            log::error(&format!("Failed to toggle mute: {}", e));
+            tray::show_balloon("Audio Output Switcher", &format!("Couldn't toggle mute: {}", e));
+        }
+    }
+}
+
+/// Show the current output device on screen until the peek hotkey's key is let go.
+fn peek_device(cfg: &config::Config) {
+    let Some(Ok((_, key))) = cfg.peek_hotkey.as_deref().map(hotkey::parse_hotkey) else {
+        return;
+    };
+    let id = audio::get_default_device_id(audio::Direction::Render).ok();
+    let slot = id.as_deref().map_or(config::Slot::Other, |id| cfg.slot(id));
+    let name = id
+        .as_deref()
+        .and_then(audio::get_device_name)
+        .unwrap_or_else(|| "No output device".to_string());
+    overlay::show(tray::device_icon(id.as_deref(), slot), &name, key);
+}
+
+/// Activate the `index`-th profile (by name order) and switch to its speakers.
+fn activate_profile(cfg: &mut config::Config, index: usize, trigger: log::Trigger) {
+    let Some(name) = cfg.profiles.keys().nth(index).cloned() else {
+        return;
+    };
+    cfg.activate_profile(&name);
+    tray::set_device_ring(&cfg.device_ring());
+    tray::set_profiles(cfg.profiles.keys().cloned().collect(), cfg.active_profile.clone());
+    if let Err(e) = config::save(cfg) {
+        eprintln!("Failed to save config: {}", e);
+    }
+
+    match set_default(cfg, &cfg.speakers, trigger) {
+        Ok(()) => {
+            tray::update_state(cfg.slot(&cfg.speakers));
+            tray::show_balloon("Audio Output Switcher", &format!("Profile: {}", name));
+            play_switch_sound(SwitchSound::Switched(cfg.slot(&cfg.speakers)), false);
+        }
+        Err(e) => eprintln!("Failed to switch device for profile '{}': {}", name, e),
+    }
+}
+
+/// Apply `repeat_action` for a request to switch to `target_id`. Returns the device to switch
+/// to, or None if no switch is needed (after replaying the sound for "resound").
+fn resolve_repeat<'a>(cfg: &'a config::Config, target_id: &'a str, sync_sound: bool) -> Option<&'a str> {
+    let Ok(current_id) = audio::get_default_device_id(audio::Direction::Render) else {
+        return Some(target_id);
+    };
+    if current_id != target_id {
+        return Some(target_id);
+    }
+    match cfg.repeat_action {
+        config::RepeatAction::Nothing => None,
+        config::RepeatAction::Resound => {
+            play_switch_sound(SwitchSound::Switched(cfg.slot(target_id)), sync_sound);
+            None
+        }
+        config::RepeatAction::Cycle => Some(cfg.cycle_target(&current_id, 1)),
+    }
+}
+
+/// Switch directly to a device picked from the tray menu, enabling it first if it's disabled.
+fn switch_to_device(cfg: &config::Config, device: &audio::AudioDevice, trigger: log::Trigger) {
+    match resolve_repeat(cfg, &device.id, false) {
+        None => return,
+        Some(target_id) if target_id != device.id => {
+            cycle_device(cfg, 1, trigger);
+            return;
+        }
+        Some(_) => {}
+    }
+
+    if device.state == audio::DeviceState::Disabled
+        && let Err(e) = audio::enable_device(&device.id)
+    {
+        show_error(&format!(
+            "Could not enable '{}': {}\n\nEnabling a disabled device usually requires \
+             running Audio Output Switcher as administrator.",
+            device.name, e
+        ));
+        return;
+    }
+
+    match set_default(cfg, &device.id, trigger) {
+        Ok(()) => {
+            tray::update_state(cfg.slot(&device.id));
+            play_switch_sound(SwitchSound::Switched(cfg.slot(&device.id)), false);
+        }
+        Err(e) => {
+            play_switch_sound(SwitchSound::Failed, false);
+            show_error(&format!("Could not switch to '{}': {}", device.name, e));
+        }
+    }
+}
+
+/// Show an error dialog (there's no console to print to in tray mode).
+fn show_error(text: &str) {
+    let text_wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    let title_wide: Vec<u16> = "Audio Output Switcher"
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    unsafe {
+        MessageBoxW(
+            None,
+            PCWSTR(text_wide.as_ptr()),
+            PCWSTR(title_wide.as_ptr()),
+            MB_OK | MB_ICONERROR,
+        );
+    }
+}
+
+/// Ask a yes/no question in a warning dialog; true if the user picks Yes.
+fn confirm_dialog(text: &str) -> bool {
+    let text_wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        MessageBoxW(None, PCWSTR(text_wide.as_ptr()), w!("Audio Output Switcher"), MB_YESNO | MB_ICONWARNING)
+            == IDYES
+    }
+}
+
+fn set_switch_sound(cfg: &config::Config) {
+    tray::set_notify_sound(cfg.notify_sound);
+    let path = |p: &Option<String>| p.as_ref().map(PathBuf::from);
+    *SOUND_PATHS.lock().unwrap() = SoundPaths {
+        any: path(&cfg.notify_sound_path),
+        speakers: path(&cfg.speakers_sound_path),
+        headphones: path(&cfg.headphones_sound_path),
+    };
+}
+
+fn play_switch_sound(sound: SwitchSound, sync: bool) {
+    if !tray::is_notify_sound() || QUIET.load(Ordering::Acquire) {
+        return;
+    }
+    let slot = match sound {
+        SwitchSound::Switched(slot) => slot,
+        SwitchSound::Failed => {
+            play_wav_bytes(FAILED_SOUND, "failed", sync);
+            return;
+        }
+    };
+    // The slot's own file, then notify_sound_path, then notify.wav next to the exe, otherwise
+    // the embedded default (a different one for the headphones)
+    let configured = {
+        let paths = SOUND_PATHS.lock().unwrap();
+        let own = match slot {
+            config::Slot::Speakers => paths.speakers.clone(),
+            config::Slot::Headphones => paths.headphones.clone(),
+            config::Slot::Other => None,
+        };
+        own.into_iter().chain(paths.any.clone()).find(|p| {
+            let exists = p.exists();
+            if !exists {
+                eprintln!("Sound file {} not found; using the default sound", p.display());
+            }
+            exists
+        })
+    };
+    let notify_path = configured.or_else(|| {
+        std::env::current_exe()
+            .ok()
+            .and_then(|p| p.parent().map(|d| d.join("notify.wav")))
+            .filter(|p| p.exists())
+    });
+    if let Some(notify_path) = notify_path {
+        play_wav_file(&notify_path, sync);
+    } else if slot == config::Slot::Headphones {
+        play_wav_bytes(HEADPHONES_SOUND, "headphones", sync);
+    } else {
+        play_wav_bytes(SWITCH_SOUND, "switch", sync);
+    }
+}
+
+fn play_wav_file(path: &Path, sync: bool) -> bool {
+    let path_wide: Vec<u16> = path.to_string_lossy().encode_utf16().chain(std::iter::once(0)).collect();
+    let flags = if sync { SND_FILENAME } else { SND_FILENAME | SND_ASYNC };
+    unsafe { PlaySoundW(PCWSTR(path_wide.as_ptr()), None, flags).as_bool() }
+}
+
+/// Play an embedded WAV image. If PlaySoundW refuses it from memory, it's written to
+/// %TEMP%\audio-output-switcher-<name>.wav and played from there instead.
+fn play_wav_bytes(wav: &'static [u8], name: &str, sync: bool) {
+    // With SND_MEMORY the "string" argument is really a pointer to the WAV image; the
+    // binding only takes PCWSTR, so the byte pointer is passed through unchanged. The data is
+    // 'static, which SND_ASYNC needs since playback outlives this call.
+    let flags = if sync { SND_MEMORY } else { SND_MEMORY | SND_ASYNC };
+    if unsafe { PlaySoundW(PCWSTR(wav.as_ptr().cast()), None, flags) }.as_bool() {
+        return;
+    }
+
+    let path = std::env::temp_dir().join(format!("audio-output-switcher-{}.wav", name));
+    let written = fs::metadata(&path).is_ok_and(|m| m.len() == wav.len() as u64) || fs::write(&path, wav).is_ok();
+    if !written || !play_wav_file(&path, sync) {
+        log::error(&format!("Couldn't play the {} sound from memory or {}", name, path.display()));
+    }
+}
+
+/// Interactive device/hotkey setup, in a small window (console prompts if that can't be
+/// created). Settings not covered by it are carried over from `previous` when reconfiguring.
+fn run_setup(previous: Option<&config::Config>) -> Option<config::Config> {
+    // Disabled devices are listed too (marked as such) and enabled if picked. Right after
+    // login, USB devices may not be enumerated yet, so wait a while for a second one.
+    let wait_secs = previous.map_or_else(config::default_device_wait_secs, |p| p.device_wait_secs);
+    let mut waited = 0;
+    let devices = loop {
+        let devices = match audio::list_devices_with_disabled() {
+            Ok(devices) => devices,
+            Err(e) => {
+                show_error(&format!("Failed to enumerate audio devices: {}", e));
+                return None;
+            }
+        };
+        if devices.len() >= 2 {
+            break devices;
+        }
+        if waited >= wait_secs {
+            show_error(&format!(
+                "Need at least 2 audio output devices. Found {} after waiting {} seconds.",
+                devices.len(),
+                waited
+            ));
+            return None;
+        }
+        if waited == 0 {
+            log::info(&format!("Only {} output device(s) so far; waiting for audio devices...", devices.len()));
+            tray::show_balloon("Audio Output Switcher", "Waiting for audio devices...");
+        }
+        std::thread::sleep(Duration::from_secs(1));
+        waited += 1;
+    };
+
+    // Preselect the current pair when reconfiguring
+    let position = |id: Option<&String>| id.and_then(|id| devices.iter().position(|d| &d.id == id));
+    let initial = (
+        position(previous.map(|p| &p.speakers)).unwrap_or(0),
+        position(previous.map(|p| &p.headphones)).unwrap_or(1),
+    );
+    let hotkey_str = previous.map_or("Ctrl+Alt+S", |p| p.hotkey.as_str());
+    let profile = previous.and_then(|p| p.active_profile.as_deref()).unwrap_or("");
+
+    let choice = match setup_dialog::run(&devices, initial, hotkey_str, profile) {
+        Ok(choice) => choice?,
+        Err(e) => {
+            log::error(&format!("Setup window failed ({}); using console setup", e));
+            unsafe { let _ = AllocConsole(); }
+            let choice = run_console_setup(&devices);
+            unsafe { let _ = FreeConsole(); }
+            choice?
+        }
+    };
+    let (a, b) = (choice.speakers, choice.headphones);
+    for device in [&devices[a], &devices[b]] {
+        if device.state == audio::DeviceState::Disabled
+            && let Err(e) = audio::enable_device(&device.id)
+        {
+            show_error(&format!(
+                "Could not enable '{}': {}\n\nIt's still saved. Enable it in the Sound control panel, \
+                 or run Audio Output Switcher as administrator, before switching to it.",
+                device.name, e
+            ));
+        }
+    }
+
+    let mut cfg = config::Config {
+        speakers: devices[a].id.clone(),
+        headphones: devices[b].id.clone(),
+        speakers_name: Some(devices[a].name.clone()),
+        headphones_name: Some(devices[b].name.clone()),
+        hotkey: choice.hotkey,
+        ..previous.cloned().unwrap_or_default()
+    };
+
+    // A profile name saves the pair under it (creating or updating the profile, keeping its
+    // hotkey) and makes it the active one
+    if choice.profile.is_empty() {
+        cfg.active_profile = None;
+    } else {
+        let hotkey = cfg.profiles.get(&choice.profile).and_then(|p| p.hotkey.clone());
+        cfg.profiles.insert(
+            choice.profile.clone(),
+            config::Profile { speakers: cfg.speakers.clone(), headphones: cfg.headphones.clone(), hotkey },
+        );
+        cfg.active_profile = Some(choice.profile);
+    }
+
+    if let Err(e) = config::save(&cfg) {
+        show_error(&format!("Failed to save config: {}", e));
+        return None;
+    }
+    log::info(&format!(
+        "Setup saved: speakers '{}', headphones '{}', hotkey {}",
+        devices[a].name, devices[b].name, cfg.hotkey
+    ));
+
+    Some(cfg)
+}
+
+/// Numbered stdin prompts for setup, used when the setup window can't be shown.
+fn run_console_setup(devices: &[audio::AudioDevice]) -> Option<setup_dialog::SetupChoice> {
+    println!("Audio Output Switcher setup\n");
+    println!("Available audio output devices:");
+    for (i, dev) in devices.iter().enumerate() {
+        println!("  [{}] {}", i + 1, setup_dialog::device_label(dev));
+    }
+    println!();
+
+    let a = prompt_device_choice("Select Speakers (number or part of the name): ", devices)?;
+    let b = prompt_device_choice("Select Headphones (number or part of the name): ", devices)?;
+
+    if a == b {
+        eprintln!("Speakers and Headphones must be different devices.");
+        pause();
+        return None;
+    }
+
+    let hotkey = prompt_hotkey()?;
+
+    print!("Save as profile (name, or Enter to skip): ");
+    io::stdout().flush().ok()?;
+    let mut profile = String::new();
+    io::stdin().read_line(&mut profile).ok()?;
+
+    Some(setup_dialog::SetupChoice { speakers: a, headphones: b, hotkey, profile: profile.trim().to_string() })
+}
+
+fn prompt_hotkey() -> Option<String> {
+    loop {
+        print!("Type a hotkey, P to press it instead, or Enter for Ctrl+Alt+S: ");
+        io::stdout().flush().ok()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).ok()?;
+        let input = input.trim();
+
+        let hotkey_str = if input.is_empty() {
+            "Ctrl+Alt+S".to_string()
+        } else if input.eq_ignore_ascii_case("p") {
+            println!("Press the key combination now (Esc to cancel; gives up after 10 seconds)...");
+            match hotkey::capture() {
+                Some(captured) => {
+                    println!("Captured {}", captured);
+                    captured
+                }
+                None => continue,
+            }
+        } else {
+            input.to_string()
+        };
+
+        match hotkey::try_register_probe(&hotkey_str) {
+            Ok(()) => return Some(hotkey_str),
+            Err(e @ error::Error::HotkeyParse(_)) => {
+                eprintln!("Invalid hotkey '{}': {}", hotkey_str, e);
+                eprintln!("Format: Modifier+Modifier+Key (e.g. Ctrl+Alt+S, Ctrl+Shift+F1)");
+            }
+            Err(error::Error::HotkeyInUse(_)) => {
+                eprintln!("Hotkey '{}' is already used by another program; pick a different combination.", hotkey_str);
+            }
+            Err(e) => eprintln!("Hotkey '{}' can't be registered: {}", hotkey_str, e),
+        }
+    }
+}
+
+/// Ask for a device by its number in the list or a case-insensitive piece of its name,
+/// asking again until exactly one device matches. None if stdin is closed.
+fn prompt_device_choice(prompt: &str, devices: &[audio::AudioDevice]) -> Option<usize> {
+    loop {
+        print!("{}", prompt);
+        io::stdout().flush().ok()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input).ok()? == 0 {
+            return None;
+        }
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+
+        if let Ok(n) = input.parse::<usize>() {
+            if (1..=devices.len()).contains(&n) {
+                return Some(n - 1);
+            }
+            eprintln!("Invalid choice: {} (pick 1-{})", n, devices.len());
+            continue;
+        }
+
+        let needle = input.to_lowercase();
+        let matches: Vec<usize> = (0..devices.len())
+            .filter(|&i| devices[i].name.to_lowercase().contains(&needle))
+            .collect();
+        match matches.as_slice() {
+            [i] => {
+                println!("  -> {}", devices[*i].name);
+                return Some(*i);
+            }
+            [] => eprintln!("No device matches '{}'", input),
+            _ => eprintln!(
+                "'{}' matches several devices:\n  {}",
+                input,
+                matches.iter().map(|&i| devices[i].name.as_str()).collect::<Vec<_>>().join("\n  ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Backend over a fixed set of active devices that records switches instead of making them.
+    struct MockAudio {
+        devices: Vec<&'static str>,
+        default_id: RefCell<String>,
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl MockAudio {
+        fn new(devices: &[&'static str], default_id: &str) -> Self {
+            MockAudio {
+                devices: devices.to_vec(),
+                default_id: RefCell::new(default_id.to_string()),
+                calls: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.borrow().clone()
+        }
+    }
+
+    impl audio::AudioBackend for MockAudio {
+        fn list_devices(&self, _direction: audio::Direction) -> error::Result<Vec<audio::AudioDevice>> {
+            Ok(self
+                .devices
+                .iter()
+                .map(|&id| audio::AudioDevice {
+                    id: id.to_string(),
+                    name: id.to_string(),
+                    state: audio::DeviceState::Active,
+                    connected: true,
+                    form_factor: audio::FormFactor::Other,
+                    container_id: None,
+                })
+                .collect())
+        }
+
+        fn get_default_device_id(&self, _direction: audio::Direction, _role: audio::Role) -> error::Result<String> {
+            Ok(self.default_id.borrow().clone())
+        }
+
+        fn set_default_device(&self, device_id: &str, _roles: &[audio::Role]) -> error::Result<()> {
+            self.calls.borrow_mut().push(format!("set {}", device_id));
+            if !self.devices.contains(&device_id) {
+                return Err(error::Error::DeviceNotFound(device_id.to_string()));
+            }
+            *self.default_id.borrow_mut() = device_id.to_string();
+            Ok(())
+        }
+
+        fn set_default_device_muted(&self, from_id: &str, to_id: &str, roles: &[audio::Role]) -> error::Result<()> {
+            self.calls.borrow_mut().push(format!("mute {}", from_id));
+            self.set_default_device(to_id, roles)
+        }
+    }
+
+    fn pair_config() -> config::Config {
+        config::Config { speakers: "spk".to_string(), headphones: "hp".to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn toggle_from_speakers_picks_headphones() {
+        let cfg = pair_config();
+        let backend = MockAudio::new(&["spk", "hp"], "spk");
+
+        let target = next_device(&backend, &cfg, audio::Role::Console, 1).unwrap();
+        assert_eq!(target, "hp");
+        set_default_with_retries(&backend, &cfg, target).unwrap();
+        assert_eq!(backend.calls(), ["set hp"]);
+        assert_eq!(*backend.default_id.borrow(), "hp");
+    }
+
+    #[test]
+    fn missing_device_errors_without_retrying() {
+        let cfg = config::Config { switch_retries: 2, switch_retry_delay_ms: 0, ..pair_config() };
+        let backend = MockAudio::new(&["spk"], "spk");
+
+        let result = set_default_with_retries(&backend, &cfg, "hp");
+        assert!(matches!(result, Err(error::Error::DeviceNotFound(id)) if id == "hp"));
+        assert_eq!(backend.calls(), ["set hp"]);
+        assert_eq!(*backend.default_id.borrow(), "spk");
+    }
+
+    #[test]
+    fn ring_wraps_both_ways() {
+        let cfg = config::Config {
+            devices: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            ..pair_config()
+        };
+
+        let backend = MockAudio::new(&["a", "b", "c"], "c");
+        assert_eq!(next_device(&backend, &cfg, audio::Role::Console, 1).unwrap(), "a");
+
+        let backend = MockAudio::new(&["a", "b", "c"], "a");
+        assert_eq!(next_device(&backend, &cfg, audio::Role::Console, -1).unwrap(), "c");
+    }
+
+    #[test]
+    fn cooldown_without_previous_toggle() {
+        assert!(!in_cooldown(None, Instant::now(), Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn cooldown_window() {
+        let last = Instant::now();
+        let cooldown = Duration::from_millis(250);
+        assert!(in_cooldown(Some(last), last, cooldown));
+        assert!(in_cooldown(Some(last), last + Duration::from_millis(249), cooldown));
+        assert!(!in_cooldown(Some(last), last + cooldown, cooldown));
+        assert!(!in_cooldown(Some(last), last + Duration::from_secs(1), cooldown));
+    }
+
+    #[test]
+    fn zero_cooldown_never_blocks() {
+        let last = Instant::now();
+        assert!(!in_cooldown(Some(last), last, Duration::ZERO));
+    }
+
+    #[test]
+    fn cooldown_stretches_for_repeat_hotkey() {
+        let cfg = config::Config { debounce_ms: 50, ..Default::default() };
+        assert_eq!(toggle_cooldown(&cfg), Duration::from_millis(50));
+        let cfg = config::Config { repeat_hotkey: true, ..cfg };
+        assert_eq!(toggle_cooldown(&cfg), HOLD_CYCLE_INTERVAL.max(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn mute_during_switch_goes_through_backend() {
+        let cfg = config::Config { mute_during_switch: true, ..pair_config() };
+        let backend = MockAudio::new(&["spk", "hp"], "spk");
+
+        set_default_with_retries(&backend, &cfg, "hp").unwrap();
+        assert_eq!(backend.calls(), ["mute spk", "set hp"]);
+    }
 }
@@ -0,0 +1,160 @@
+use std::cell::RefCell;
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{COLORREF, HWND, LPARAM, LRESULT, RECT, WPARAM};
+use windows::Win32::Graphics::Gdi::{
+    BeginPaint, CreateSolidBrush, DeleteObject, DrawTextW, EndPaint, FillRect, GetStockObject,
+    InvalidateRect, SelectObject, SetBkMode, SetTextColor, DEFAULT_GUI_FONT, DT_END_ELLIPSIS,
+    DT_LEFT, DT_SINGLELINE, DT_VCENTER, PAINTSTRUCT, TRANSPARENT,
+};
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VIRTUAL_KEY};
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::log;
+
+const PEEK_WINDOW_CLASS: &str = "AudioSwitcherPeek";
+
+const WIDTH: i32 = 320;
+const HEIGHT: i32 = 56;
+const ICON_SIZE: i32 = 32;
+const PADDING: i32 = 12;
+// Gap between the overlay and the bottom of the work area
+const BOTTOM_MARGIN: i32 = 96;
+const OPACITY: u8 = 220;
+
+// The peek hotkey's key is polled this often to notice it being released
+const RELEASE_TIMER_ID: usize = 1;
+const RELEASE_POLL_MS: u32 = 50;
+
+struct Peek {
+    hwnd: HWND,
+    icon: HICON,
+    text: Vec<u16>,
+    key: VIRTUAL_KEY,
+}
+
+thread_local! {
+    // Created on the first peek and kept (hidden) afterwards
+    static PEEK: RefCell<Option<Peek>> = const { RefCell::new(None) };
+}
+
+/// Show `icon` and `text` in a small click-through window near the bottom of the screen until
+/// `key` is released. Creates the window the first time; call on the UI thread.
+pub fn show(icon: HICON, text: &str, key: VIRTUAL_KEY) {
+    let hwnd = match PEEK.with(|peek| peek.borrow().as_ref().map(|p| p.hwnd)) {
+        Some(hwnd) => hwnd,
+        None => match create_window() {
+            Ok(hwnd) => hwnd,
+            Err(e) => {
+                log::error(&format!("Failed to create the peek window: {}", e));
+                return;
+            }
+        },
+    };
+    PEEK.with(|peek| {
+        *peek.borrow_mut() = Some(Peek { hwnd, icon, text: text.encode_utf16().collect(), key });
+    });
+
+    let mut work_area = RECT::default();
+    unsafe {
+        let _ = SystemParametersInfoW(
+            SPI_GETWORKAREA,
+            0,
+            Some(&mut work_area as *mut RECT as *mut _),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        );
+        let x = work_area.left + (work_area.right - work_area.left - WIDTH) / 2;
+        let y = work_area.bottom - HEIGHT - BOTTOM_MARGIN;
+        let _ = SetWindowPos(hwnd, Some(HWND_TOPMOST), x, y, WIDTH, HEIGHT, SWP_NOACTIVATE | SWP_SHOWWINDOW);
+        let _ = InvalidateRect(Some(hwnd), None, true);
+        SetTimer(Some(hwnd), RELEASE_TIMER_ID, RELEASE_POLL_MS, None);
+    }
+}
+
+/// Hide the peek window if it's showing.
+pub fn hide() {
+    if let Some(hwnd) = PEEK.with(|peek| peek.borrow().as_ref().map(|p| p.hwnd)) {
+        unsafe {
+            let _ = KillTimer(Some(hwnd), RELEASE_TIMER_ID);
+            let _ = ShowWindow(hwnd, SW_HIDE);
+        }
+    }
+}
+
+fn create_window() -> windows::core::Result<HWND> {
+    unsafe {
+        let class_name: Vec<u16> = PEEK_WINDOW_CLASS.encode_utf16().chain(std::iter::once(0)).collect();
+        let wc = WNDCLASSEXW {
+            cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+            lpfnWndProc: Some(wndproc),
+            lpszClassName: PCWSTR(class_name.as_ptr()),
+            ..Default::default()
+        };
+        RegisterClassExW(&wc);
+
+        // Layered + transparent makes it click-through; tool window keeps it off the taskbar
+        let hwnd = CreateWindowExW(
+            WS_EX_LAYERED | WS_EX_TRANSPARENT | WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE,
+            PCWSTR(class_name.as_ptr()),
+            PCWSTR::null(),
+            WS_POPUP,
+            0,
+            0,
+            WIDTH,
+            HEIGHT,
+            None,
+            None,
+            None,
+            None,
+        )?;
+        SetLayeredWindowAttributes(hwnd, COLORREF(0), OPACITY, LWA_ALPHA)?;
+        Ok(hwnd)
+    }
+}
+
+unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_TIMER if wparam.0 == RELEASE_TIMER_ID => {
+            let key = PEEK.with(|peek| peek.borrow().as_ref().map(|p| p.key));
+            let held = key.is_some_and(|key| unsafe { GetAsyncKeyState(key.0 as i32) } as u16 & 0x8000 != 0);
+            if !held {
+                hide();
+            }
+            LRESULT(0)
+        }
+        WM_PAINT => {
+            unsafe { paint(hwnd) };
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+    }
+}
+
+/// Dark background, the device icon on the left, and its name beside it.
+unsafe fn paint(hwnd: HWND) {
+    let mut ps = PAINTSTRUCT::default();
+    unsafe {
+        let hdc = BeginPaint(hwnd, &mut ps);
+        let background = CreateSolidBrush(COLORREF(0x0020_2020));
+        let mut rect = RECT { left: 0, top: 0, right: WIDTH, bottom: HEIGHT };
+        FillRect(hdc, &rect, background);
+        let _ = DeleteObject(background.into());
+
+        PEEK.with(|peek| {
+            let mut peek = peek.borrow_mut();
+            let Some(peek) = peek.as_mut() else {
+                return;
+            };
+            let _ = DrawIconEx(hdc, PADDING, (HEIGHT - ICON_SIZE) / 2, peek.icon, ICON_SIZE, ICON_SIZE, 0, None, DI_NORMAL);
+
+            SelectObject(hdc, GetStockObject(DEFAULT_GUI_FONT));
+            SetTextColor(hdc, COLORREF(0x00FF_FFFF));
+            SetBkMode(hdc, TRANSPARENT);
+            rect.left = PADDING * 2 + ICON_SIZE;
+            rect.right -= PADDING;
+            DrawTextW(hdc, &mut peek.text, &mut rect, DT_LEFT | DT_VCENTER | DT_SINGLELINE | DT_END_ELLIPSIS);
+        });
+
+        let _ = EndPaint(hwnd, &ps);
+    }
+}
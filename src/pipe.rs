@@ -0,0 +1,115 @@
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use windows::core::w;
+use windows::Win32::Foundation::{CloseHandle, ERROR_PIPE_CONNECTED, HANDLE, INVALID_HANDLE_VALUE};
+use windows::Win32::Storage::FileSystem::{FlushFileBuffers, ReadFile, WriteFile, PIPE_ACCESS_DUPLEX};
+use windows::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_READMODE_BYTE,
+    PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_WAIT,
+};
+
+use crate::log;
+use crate::tray;
+
+// How long a client waits for the UI thread to carry out its command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A line command read from the pipe.
+pub enum Command {
+    Get,
+    Toggle,
+    Set(String),
+}
+
+// Command waiting for the UI thread, with where to send its reply line
+static PENDING: Mutex<Option<(Command, Sender<String>)>> = Mutex::new(None);
+
+/// Serve `\\.\pipe\AudioSwitcher` from a background thread. Each line a client writes (`get`,
+/// `toggle`, or `set <name>`) is posted to the main loop as WM_APP_PIPE_COMMAND, and the reply
+/// (the current device name, or `error: ...`) is written back as one line.
+pub fn start() {
+    std::thread::spawn(|| {
+        loop {
+            let pipe = unsafe {
+                CreateNamedPipeW(
+                    w!(r"\\.\pipe\AudioSwitcher"),
+                    PIPE_ACCESS_DUPLEX,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    1,
+                    4096,
+                    4096,
+                    0,
+                    None,
+                )
+            };
+            if pipe == INVALID_HANDLE_VALUE {
+                log::error(&format!("Failed to create the command pipe: {}", windows::core::Error::from_win32()));
+                return;
+            }
+
+            // ERROR_PIPE_CONNECTED means a client got in between create and connect
+            let connected = match unsafe { ConnectNamedPipe(pipe, None) } {
+                Ok(()) => true,
+                Err(e) => e.code() == ERROR_PIPE_CONNECTED.to_hresult(),
+            };
+            if connected {
+                serve_client(pipe);
+            }
+            unsafe {
+                let _ = DisconnectNamedPipe(pipe);
+                let _ = CloseHandle(pipe);
+            }
+        }
+    });
+}
+
+/// The command posted with the last WM_APP_PIPE_COMMAND and the channel for its reply.
+pub fn take_command() -> Option<(Command, Sender<String>)> {
+    PENDING.lock().unwrap().take()
+}
+
+/// Answer newline-terminated commands until the client disconnects.
+fn serve_client(pipe: HANDLE) {
+    let mut pending = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let mut read = 0u32;
+        if unsafe { ReadFile(pipe, Some(&mut buf), Some(&mut read), None) }.is_err() || read == 0 {
+            return;
+        }
+        pending.extend_from_slice(&buf[..read as usize]);
+
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let reply = run_command(String::from_utf8_lossy(&line).trim());
+            let reply = format!("{}\n", reply);
+            unsafe {
+                if WriteFile(pipe, Some(reply.as_bytes()), None, None).is_err() {
+                    return;
+                }
+                let _ = FlushFileBuffers(pipe);
+            }
+        }
+    }
+}
+
+/// Hand a command line to the UI thread (where the COM calls belong) and wait for its reply.
+fn run_command(line: &str) -> String {
+    let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let command = match word.to_ascii_lowercase().as_str() {
+        "get" => Command::Get,
+        "toggle" => Command::Toggle,
+        "set" if !rest.trim().is_empty() => Command::Set(rest.trim().to_string()),
+        "set" => return "error: usage: set <name or number>".to_string(),
+        _ => return format!("error: unknown command '{}' (expected get, toggle, or set <name>)", word),
+    };
+
+    let (reply_tx, reply_rx) = mpsc::channel();
+    *PENDING.lock().unwrap() = Some((command, reply_tx));
+    tray::post_to_main(tray::WM_APP_PIPE_COMMAND);
+    reply_rx
+        .recv_timeout(REPLY_TIMEOUT)
+        .unwrap_or_else(|_| "error: timed out".to_string())
+}
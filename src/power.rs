@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use windows::Win32::Foundation::{HANDLE, HWND, LPARAM};
+use windows::Win32::System::Power::{
+    GetSystemPowerStatus, RegisterPowerSettingNotification, UnregisterPowerSettingNotification,
+    HPOWERNOTIFY, POWERBROADCAST_SETTING, SYSTEM_POWER_STATUS,
+};
+use windows::Win32::System::SystemServices::GUID_ACDC_POWER_SOURCE;
+use windows::Win32::UI::WindowsAndMessaging::DEVICE_NOTIFY_WINDOW_HANDLE;
+
+use crate::log;
+
+// HPOWERNOTIFY from RegisterPowerSettingNotification (0 = not registered)
+static POWER_NOTIFY: AtomicIsize = AtomicIsize::new(0);
+
+/// Ask Windows to send WM_POWERBROADCAST to `hwnd` whenever the AC/battery source changes.
+pub fn register(hwnd: HWND) {
+    unsafe {
+        match RegisterPowerSettingNotification(
+            HANDLE(hwnd.0),
+            &GUID_ACDC_POWER_SOURCE,
+            DEVICE_NOTIFY_WINDOW_HANDLE,
+        ) {
+            Ok(handle) => POWER_NOTIFY.store(handle.0, Ordering::Release),
+            Err(e) => log::error(&format!("Failed to register for power notifications: {}", e)),
+        }
+    }
+}
+
+pub fn unregister() {
+    let handle = POWER_NOTIFY.swap(0, Ordering::AcqRel);
+    if handle != 0 {
+        unsafe {
+            let _ = UnregisterPowerSettingNotification(HPOWERNOTIFY(handle));
+        }
+    }
+}
+
+/// Current power source: Some(true) on battery, Some(false) on AC, None if unknown.
+pub fn is_on_battery() -> Option<bool> {
+    let mut status = SYSTEM_POWER_STATUS::default();
+    unsafe { GetSystemPowerStatus(&mut status).ok()? };
+    match status.ACLineStatus {
+        0 => Some(true),
+        1 => Some(false),
+        _ => None,
+    }
+}
+
+/// Decode a PBT_POWERSETTINGCHANGE lparam. Returns Some(on_battery) for AC/DC source changes.
+///
+/// # Safety
+/// `lparam` must be the POWERBROADCAST_SETTING pointer from a PBT_POWERSETTINGCHANGE message.
+pub unsafe fn parse_setting_change(lparam: LPARAM) -> Option<bool> {
+    let setting = unsafe { (lparam.0 as *const POWERBROADCAST_SETTING).as_ref()? };
+    if setting.PowerSetting != GUID_ACDC_POWER_SOURCE || setting.DataLength < 4 {
+        return None;
+    }
+    // Data is a DWORD: 0 = AC, 1 = battery (DC), 2 = short-term source such as a UPS
+    let value = unsafe { std::ptr::read_unaligned(setting.Data.as_ptr() as *const u32) };
+    Some(value != 0)
+}
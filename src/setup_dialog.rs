@@ -0,0 +1,315 @@
+use std::cell::RefCell;
+
+use windows::core::{w, Result, PCWSTR};
+use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::Graphics::Gdi::{GetStockObject, COLOR_BTNFACE, DEFAULT_GUI_FONT, HBRUSH};
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::audio::{AudioDevice, DeviceState};
+use crate::error::Error;
+use crate::hotkey;
+
+const SETUP_WINDOW_CLASS: &str = "AudioSwitcherSetup";
+
+// Child control IDs (IDOK/IDCANCEL are used for the buttons)
+const IDC_SPEAKERS: i32 = 101;
+const IDC_HEADPHONES: i32 = 102;
+const IDC_HOTKEY: i32 = 103;
+const IDC_CAPTURE: i32 = 104;
+const IDC_PROFILE: i32 = 105;
+
+/// What the user picked: indices into the device list, the hotkey string, and a profile name
+/// to save the pair under (empty for none).
+pub struct SetupChoice {
+    pub speakers: usize,
+    pub headphones: usize,
+    pub hotkey: String,
+    pub profile: String,
+}
+
+struct DialogState {
+    device_count: usize,
+    result: Option<SetupChoice>,
+    done: bool,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<DialogState>> = const { RefCell::new(None) };
+}
+
+/// Show a window for picking the speakers, headphones, and hotkey with the mouse, and block
+/// until it's closed. Returns Ok(None) if the user cancelled, or Err if the window couldn't
+/// be created (callers fall back to console prompts).
+///
+/// `initial` preselects the speakers/headphones rows; `hotkey_str` and `profile` pre-fill the
+/// hotkey and profile boxes.
+pub fn run(
+    devices: &[AudioDevice],
+    initial: (usize, usize),
+    hotkey_str: &str,
+    profile: &str,
+) -> Result<Option<SetupChoice>> {
+    unsafe {
+        let class_name = wide_str(SETUP_WINDOW_CLASS);
+        let wc = WNDCLASSEXW {
+            cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+            lpfnWndProc: Some(wndproc),
+            lpszClassName: PCWSTR(class_name.as_ptr()),
+            hCursor: LoadCursorW(None, IDC_ARROW)?,
+            hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize as *mut _),
+            ..Default::default()
+        };
+        // Fails harmlessly if a previous setup already registered it
+        RegisterClassExW(&wc);
+
+        let hwnd = CreateWindowExW(
+            WS_EX_DLGMODALFRAME,
+            PCWSTR(class_name.as_ptr()),
+            w!("Audio Output Switcher Setup"),
+            WS_CAPTION | WS_SYSMENU,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            440,
+            496,
+            None,
+            None,
+            None,
+            None,
+        )?;
+
+        let font = GetStockObject(DEFAULT_GUI_FONT);
+        let child = |class: PCWSTR, text: PCWSTR, style: WINDOW_STYLE, y: i32, height: i32, id: i32| {
+            let ctl = CreateWindowExW(
+                if class == w!("LISTBOX") || class == w!("EDIT") { WS_EX_CLIENTEDGE } else { WINDOW_EX_STYLE::default() },
+                class,
+                text,
+                WS_CHILD | WS_VISIBLE | style,
+                12,
+                y,
+                400,
+                height,
+                Some(hwnd),
+                Some(HMENU(id as isize as *mut _)),
+                None,
+                None,
+            );
+            if let Ok(ctl) = ctl {
+                SendMessageW(ctl, WM_SETFONT, Some(WPARAM(font.0 as usize)), Some(LPARAM(1)));
+            }
+            ctl
+        };
+
+        let list_style = WS_VSCROLL | WS_TABSTOP | WINDOW_STYLE(LBS_NOTIFY as u32);
+        child(w!("STATIC"), w!("Speakers:"), WINDOW_STYLE::default(), 10, 18, -1)?;
+        let speakers = child(w!("LISTBOX"), PCWSTR::null(), list_style, 30, 120, IDC_SPEAKERS)?;
+        child(w!("STATIC"), w!("Headphones:"), WINDOW_STYLE::default(), 160, 18, -1)?;
+        let headphones = child(w!("LISTBOX"), PCWSTR::null(), list_style, 180, 120, IDC_HEADPHONES)?;
+        child(w!("STATIC"), w!("Hotkey (type e.g. Ctrl+Alt+S, or click Capture and press it):"), WINDOW_STYLE::default(), 310, 18, -1)?;
+        let hotkey_wide = wide_str(hotkey_str);
+        child(
+            w!("EDIT"),
+            PCWSTR(hotkey_wide.as_ptr()),
+            WS_TABSTOP | WINDOW_STYLE(ES_AUTOHSCROLL as u32),
+            330,
+            22,
+            IDC_HOTKEY,
+        )?;
+        child(w!("STATIC"), w!("Save as profile (optional name):"), WINDOW_STYLE::default(), 366, 18, -1)?;
+        let profile_wide = wide_str(profile);
+        child(
+            w!("EDIT"),
+            PCWSTR(profile_wide.as_ptr()),
+            WS_TABSTOP | WINDOW_STYLE(ES_AUTOHSCROLL as u32),
+            386,
+            22,
+            IDC_PROFILE,
+        )?;
+
+        for list in [speakers, headphones] {
+            for device in devices {
+                let name = wide_str(&device_label(device));
+                SendMessageW(list, LB_ADDSTRING, None, Some(LPARAM(name.as_ptr() as isize)));
+            }
+        }
+        SendMessageW(speakers, LB_SETCURSEL, Some(WPARAM(initial.0)), None);
+        SendMessageW(headphones, LB_SETCURSEL, Some(WPARAM(initial.1)), None);
+
+        // Capture on the left, OK / Cancel right-aligned, under the profile box
+        for (text, id, x, style) in [
+            (w!("Capture..."), IDC_CAPTURE, 12, BS_PUSHBUTTON),
+            (w!("OK"), IDOK.0, 236, BS_DEFPUSHBUTTON),
+            (w!("Cancel"), IDCANCEL.0, 328, BS_PUSHBUTTON),
+        ] {
+            let button = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("BUTTON"),
+                text,
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(style as u32),
+                x,
+                422,
+                84,
+                26,
+                Some(hwnd),
+                Some(HMENU(id as isize as *mut _)),
+                None,
+                None,
+            )?;
+            SendMessageW(button, WM_SETFONT, Some(WPARAM(font.0 as usize)), Some(LPARAM(1)));
+        }
+
+        STATE.with(|s| {
+            *s.borrow_mut() = Some(DialogState { device_count: devices.len(), result: None, done: false })
+        });
+        let _ = ShowWindow(hwnd, SW_SHOW);
+        let _ = SetForegroundWindow(hwnd);
+
+        // Local modal loop; IsDialogMessageW gives Tab/Enter/Esc handling
+        let mut msg = MSG::default();
+        while !is_done() {
+            match GetMessageW(&mut msg, None, 0, 0).0 {
+                // WM_QUIT: close the dialog and post it again so the caller's loop sees it too
+                0 => {
+                    let _ = DestroyWindow(hwnd);
+                    PostQuitMessage(msg.wParam.0 as i32);
+                    break;
+                }
+                -1 => {
+                    let _ = DestroyWindow(hwnd);
+                    break;
+                }
+                _ => {
+                    if !IsDialogMessageW(hwnd, &msg).as_bool() {
+                        let _ = TranslateMessage(&msg);
+                        DispatchMessageW(&msg);
+                    }
+                }
+            }
+        }
+
+        Ok(STATE.with(|s| s.borrow_mut().take().and_then(|state| state.result)))
+    }
+}
+
+/// A device's name as listed in setup, with disabled and unplugged devices marked.
+pub fn device_label(device: &AudioDevice) -> String {
+    match device.state {
+        DeviceState::Disabled => format!("{} (disabled - will be enabled)", device.name),
+        DeviceState::Unplugged => format!("{} (unplugged)", device.name),
+        _ => device.name.clone(),
+    }
+}
+
+fn is_done() -> bool {
+    STATE.with(|s| s.borrow().as_ref().is_none_or(|state| state.done))
+}
+
+unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_COMMAND => {
+            match (wparam.0 & 0xFFFF) as i32 {
+                id if id == IDOK.0 => {
+                    if let Some(choice) = unsafe { validate(hwnd) } {
+                        STATE.with(|s| {
+                            if let Some(state) = s.borrow_mut().as_mut() {
+                                state.result = Some(choice);
+                            }
+                        });
+                        unsafe { let _ = DestroyWindow(hwnd); }
+                    }
+                }
+                IDC_CAPTURE => unsafe {
+                    // Relabel the button while the keyboard hook is waiting
+                    let button = GetDlgItem(Some(hwnd), IDC_CAPTURE).ok();
+                    if let Some(button) = button {
+                        let _ = SetWindowTextW(button, w!("Press keys..."));
+                    }
+                    if let Some(captured) = hotkey::capture()
+                        && let Ok(edit) = GetDlgItem(Some(hwnd), IDC_HOTKEY)
+                    {
+                        let text = wide_str(&captured);
+                        let _ = SetWindowTextW(edit, PCWSTR(text.as_ptr()));
+                    }
+                    if let Some(button) = button {
+                        let _ = SetWindowTextW(button, w!("Capture..."));
+                    }
+                },
+                id if id == IDCANCEL.0 => unsafe {
+                    let _ = DestroyWindow(hwnd);
+                },
+                _ => {}
+            }
+            LRESULT(0)
+        }
+        WM_CLOSE => {
+            unsafe { let _ = DestroyWindow(hwnd); }
+            LRESULT(0)
+        }
+        WM_DESTROY => {
+            // A capture still waiting for keys would otherwise keep its hook in
+            hotkey::cancel_capture();
+            STATE.with(|s| {
+                if let Some(state) = s.borrow_mut().as_mut() {
+                    state.done = true;
+                }
+            });
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+    }
+}
+
+/// Read the controls and check them, explaining any problem in a message box. Returns None
+/// (leaving the window open) if something needs fixing.
+unsafe fn validate(hwnd: HWND) -> Option<SetupChoice> {
+    let selection = |id: i32| unsafe {
+        let list = GetDlgItem(Some(hwnd), id).ok()?;
+        let index = SendMessageW(list, LB_GETCURSEL, None, None).0;
+        let count = STATE.with(|s| s.borrow().as_ref().map_or(0, |state| state.device_count));
+        (index >= 0 && (index as usize) < count).then_some(index as usize)
+    };
+
+    let (Some(speakers), Some(headphones)) = (selection(IDC_SPEAKERS), selection(IDC_HEADPHONES)) else {
+        warn(hwnd, "Pick a device for both Speakers and Headphones.");
+        return None;
+    };
+    if speakers == headphones {
+        warn(hwnd, "Speakers and Headphones must be different devices.");
+        return None;
+    }
+
+    let text = |id: i32| {
+        let mut buf = [0u16; 128];
+        let len = unsafe { GetDlgItem(Some(hwnd), id).map_or(0, |edit| GetWindowTextW(edit, &mut buf)) };
+        String::from_utf16_lossy(&buf[..len.max(0) as usize]).trim().to_string()
+    };
+    let profile = text(IDC_PROFILE);
+    let hotkey_str = match text(IDC_HOTKEY).as_str() {
+        "" => "Ctrl+Alt+S".to_string(),
+        s => s.to_string(),
+    };
+    match hotkey::try_register_probe(&hotkey_str) {
+        Ok(()) => Some(SetupChoice { speakers, headphones, hotkey: hotkey_str, profile }),
+        Err(e @ Error::HotkeyParse(_)) => {
+            warn(hwnd, &format!(
+                "Invalid hotkey '{}': {}\n\nFormat: Modifier+Modifier+Key (e.g. Ctrl+Alt+S, Ctrl+Shift+F1)",
+                hotkey_str, e
+            ));
+            None
+        }
+        Err(e) => {
+            warn(hwnd, &format!("Hotkey '{}' can't be used: {}", hotkey_str, e));
+            None
+        }
+    }
+}
+
+fn warn(hwnd: HWND, text: &str) {
+    let text = wide_str(text);
+    unsafe {
+        MessageBoxW(Some(hwnd), PCWSTR(text.as_ptr()), w!("Audio Output Switcher Setup"), MB_OK | MB_ICONWARNING);
+    }
+}
+
+fn wide_str(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
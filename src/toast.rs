@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::sync::{Mutex, Once};
+
+use windows::core::{w, IInspectable, Interface, HSTRING, PCWSTR};
+use windows::Data::Xml::Dom::XmlDocument;
+use windows::Foundation::TypedEventHandler;
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegCreateKeyExW, RegDeleteTreeW, RegOpenKeyExW, RegSetValueExW, HKEY,
+    HKEY_CURRENT_USER, KEY_READ, KEY_WRITE, REG_OPTION_NON_VOLATILE, REG_SZ,
+};
+use windows::UI::Notifications::{
+    ToastActivatedEventArgs, ToastNotification, ToastNotificationManager, ToastNotifier,
+};
+
+use crate::error::Result;
+use crate::tray;
+
+// AppUserModelID the toasts are attributed to (registered under HKCU, no shortcut needed)
+const APP_ID: &str = "PinW.AudioSwitcher";
+const APP_DISPLAY_NAME: &str = "Audio Output Switcher";
+
+// Activation argument prefix for the Undo button; the previous device ID follows it
+const UNDO_PREFIX: &str = "undo:";
+
+static REGISTER_APP_ID: Once = Once::new();
+
+// Device ID from the last Undo click, picked up by the main loop on WM_APP_UNDO
+static PENDING_UNDO: Mutex<Option<String>> = Mutex::new(None);
+
+thread_local! {
+    // Keep the notifier and last toast alive so its Activated handler stays connected
+    static LAST_TOAST: RefCell<Option<(ToastNotifier, ToastNotification)>> =
+        const { RefCell::new(None) };
+}
+
+/// Show a "Switched to ..." toast with an Undo button that reverts to `previous_id`.
+///
+/// Replaces any earlier switch toast that is still on screen.
+pub fn show_switch_toast(device_label: &str, previous_id: &str) -> Result<()> {
+    REGISTER_APP_ID.call_once(|| {
+        if let Err(e) = register_app_id() {
+            eprintln!("Failed to register toast app ID: {}", e);
+        }
+    });
+
+    let xml = format!(
+        "<toast>\
+           <visual><binding template=\"ToastGeneric\"><text>Switched to {}</text></binding></visual>\
+           <actions><action content=\"Undo\" arguments=\"{}{}\" activationType=\"foreground\"/></actions>\
+           <audio silent=\"true\"/>\
+         </toast>",
+        xml_escape(device_label),
+        UNDO_PREFIX,
+        xml_escape(previous_id),
+    );
+
+    let doc = XmlDocument::new()?;
+    doc.LoadXml(&HSTRING::from(xml))?;
+
+    let toast = ToastNotification::CreateToastNotification(&doc)?;
+    toast.SetTag(&HSTRING::from("switch"))?;
+    toast.SetExpiresOnReboot(true)?;
+    toast.Activated(&TypedEventHandler::new(
+        |_: windows::core::Ref<ToastNotification>, args: windows::core::Ref<IInspectable>| {
+            // Fires on a WinRT thread pool thread; hand the work to the UI thread
+            let args: ToastActivatedEventArgs = args.ok()?.cast()?;
+            let arguments = args.Arguments()?.to_string();
+            if let Some(device_id) = arguments.strip_prefix(UNDO_PREFIX) {
+                *PENDING_UNDO.lock().unwrap() = Some(device_id.to_string());
+                tray::post_to_main(tray::WM_APP_UNDO);
+            }
+            Ok(())
+        },
+    ))?;
+
+    let notifier = ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(APP_ID))?;
+
+    LAST_TOAST.with(|last| {
+        if let Some((old_notifier, old_toast)) = last.borrow_mut().take() {
+            let _ = old_notifier.Hide(&old_toast);
+        }
+        notifier.Show(&toast)?;
+        *last.borrow_mut() = Some((notifier, toast));
+        Ok(())
+    })
+}
+
+/// Take the device ID requested by the most recent Undo click, if any.
+pub fn take_pending_undo() -> Option<String> {
+    PENDING_UNDO.lock().unwrap().take()
+}
+
+/// Registry key (under HKEY_CURRENT_USER) holding the AppUserModelID registration.
+pub fn app_id_key() -> String {
+    format!("Software\\Classes\\AppUserModelId\\{}", APP_ID)
+}
+
+/// Whether the AppUserModelID has been registered (by showing a toast at some point).
+pub fn is_app_id_registered() -> bool {
+    let subkey = wide_str(&app_id_key());
+    let mut hkey = HKEY::default();
+    unsafe {
+        let opened = RegOpenKeyExW(HKEY_CURRENT_USER, PCWSTR(subkey.as_ptr()), None, KEY_READ, &mut hkey).is_ok();
+        if opened {
+            let _ = RegCloseKey(hkey);
+        }
+        opened
+    }
+}
+
+/// Delete the AppUserModelID registration, for uninstall.
+pub fn unregister_app_id() -> Result<()> {
+    let subkey = wide_str(&app_id_key());
+    unsafe { RegDeleteTreeW(HKEY_CURRENT_USER, PCWSTR(subkey.as_ptr())).ok()? };
+    Ok(())
+}
+
+/// Register the AppUserModelID so Windows will display toasts for an unpackaged exe.
+fn register_app_id() -> windows::core::Result<()> {
+    let subkey = wide_str(&app_id_key());
+    let display_name: Vec<u8> = wide_str(APP_DISPLAY_NAME)
+        .iter()
+        .flat_map(|c| c.to_le_bytes())
+        .collect();
+
+    unsafe {
+        let mut hkey = HKEY::default();
+        RegCreateKeyExW(
+            HKEY_CURRENT_USER,
+            PCWSTR(subkey.as_ptr()),
+            None,
+            PCWSTR::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_WRITE,
+            None,
+            &mut hkey,
+            None,
+        )
+        .ok()?;
+        let result = RegSetValueExW(hkey, w!("DisplayName"), None, REG_SZ, Some(&display_name)).ok();
+        let _ = RegCloseKey(hkey);
+        result
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn wide_str(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
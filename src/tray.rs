@@ -1,28 +1,63 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::c_void;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
 
-use windows::core::{PCWSTR, Interface, BSTR};
+use windows::core::{w, PCWSTR};
 use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM};
-use windows::Win32::System::Com::{
-    CoCreateInstance, CoInitializeEx, CoUninitialize, IPersistFile, CLSCTX_INPROC_SERVER,
-    COINIT_APARTMENTTHREADED,
+use windows::Win32::Graphics::Gdi::{
+    CreateBitmap, CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, GdiFlush, GetDC,
+    ReleaseDC, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
 };
+use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
 use windows::Win32::UI::Shell::{
-    IShellLinkW, Shell_NotifyIconW, ShellLink, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD,
-    NIM_DELETE, NIM_MODIFY, NOTIFYICONDATAW,
+    ShellExecuteW, Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP, NIIF_INFO,
+    NIM_ADD, NIM_DELETE, NIM_MODIFY, NOTIFYICONDATAW,
 };
+use windows::Win32::UI::Input::KeyboardAndMouse::GetDoubleClickTime;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
+use crate::audio::{self, AudioDevice, DeviceState};
+use crate::autostart;
+use crate::config::{self, Slot};
+use crate::error::{Error, Result};
+use crate::log;
+use crate::power;
+
 const WM_TRAYICON: u32 = WM_APP + 1;
 const TRAY_ICON_ID: u32 = 1;
+// Timer that delays the single-click toggle until a double-click can be ruled out
+const CLICK_TIMER_ID: usize = 1;
+// Timer that checks for mute changes made outside the app, for show_mute_state
+const MUTE_TIMER_ID: usize = 2;
+const MUTE_POLL_MS: u32 = 1000;
 
 // Custom messages posted from wndproc, handled in main message loop
 pub const WM_APP_TOGGLE: u32 = WM_APP + 100;
 pub const WM_APP_RECONFIGURE: u32 = WM_APP + 101;
-// Sent by CLI to update tray icon state (wparam: 1=speakers, 0=headphones)
+// Sent by CLI to update tray icon state (wparam from slot_to_wparam)
 pub const WM_APP_REFRESH_STATE: u32 = WM_APP + 102;
 pub const WM_APP_TOGGLE_SOUND: u32 = WM_APP + 103;
+// Posted by the toast Undo button (pending device ID lives in toast.rs)
+pub const WM_APP_UNDO: u32 = WM_APP + 104;
+// Posted when a "Switch to" menu entry is picked (wparam: index into the menu's device list)
+pub const WM_APP_SWITCH_TO: u32 = WM_APP + 105;
+// Posted on AC/battery transitions (wparam: 1=battery, 0=AC)
+pub const WM_APP_POWER_SOURCE: u32 = WM_APP + 106;
+// Posted by the config watcher when the config file was edited outside the app
+pub const WM_APP_RELOAD: u32 = WM_APP + 107;
+// Posted when an endpoint is plugged in or enabled (device IDs queued in main's ARRIVED_DEVICES)
+pub const WM_APP_DEVICE_ARRIVED: u32 = WM_APP + 108;
+// Posted when a "Profile" menu entry is picked (wparam: index into Config::profiles, name order)
+pub const WM_APP_PROFILE: u32 = WM_APP + 109;
+// Posted by the pipe server when a client sent a command (picked up with pipe::take_command)
+pub const WM_APP_PIPE_COMMAND: u32 = WM_APP + 110;
+// Posted when the default output changed, however it was changed (e.g. Windows' sound flyout)
+pub const WM_APP_DEFAULT_CHANGED: u32 = WM_APP + 111;
+// Posted when a new foreground app has settled (its name comes from foreground::take_app)
+pub const WM_APP_FOREGROUND: u32 = WM_APP + 112;
 
 pub const MSG_WINDOW_CLASS: &str = "AudioSwitcherMsg";
 
@@ -31,16 +66,58 @@ const IDM_RECONFIGURE: usize = 1001;
 const IDM_EXIT: usize = 1002;
 const IDM_AUTOSTART: usize = 1003;
 const IDM_NOTIFY_SOUND: usize = 1004;
+const IDM_SOUND_SETTINGS: usize = 1005;
+// "Profile" submenu entries are IDM_PROFILE_BASE + index into PROFILES
+const IDM_PROFILE_BASE: usize = 1100;
+// Device entries (top level and "Switch to" submenu) are IDM_DEVICE_BASE + index into MENU_DEVICES
+const IDM_DEVICE_BASE: usize = 2000;
 
 // Embedded ICO files (multi-resolution, built from pixel art PNGs)
 const SPEAKERS_ICO: &[u8] = include_bytes!("../assets/speakers.ico");
 const HEADPHONES_ICO: &[u8] = include_bytes!("../assets/headphones.ico");
+// Darker variants for a light taskbar, where the default ones barely show
+const SPEAKERS_LIGHT_ICO: &[u8] = include_bytes!("../assets/speakers_light.ico");
+const HEADPHONES_LIGHT_ICO: &[u8] = include_bytes!("../assets/headphones_light.ico");
+// ICO entries whose image data starts with this are PNG-compressed rather than BMP
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
 
 // Stored handles (HWND/HICON aren't Send+Sync, so use AtomicPtr)
 static MSG_HWND: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
 static SPEAKER_ICON: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
 static HEADPHONE_ICON: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+// Whether the notification-area icon was added (false in headless mode)
+static ICON_SHOWN: AtomicBool = AtomicBool::new(false);
+// Whether the light-taskbar icons are loaded
+static LIGHT_THEME: AtomicBool = AtomicBool::new(false);
+// Slot last shown (slot_to_wparam encoding), to redraw it when the theme changes
+static LAST_SLOT: AtomicUsize = AtomicUsize::new(0);
 static NOTIFY_SOUND: AtomicBool = AtomicBool::new(true);
+static USE_SYSTEM_ICONS: AtomicBool = AtomicBool::new(false);
+static SHOW_SPLIT: AtomicBool = AtomicBool::new(false);
+// Role whose default the icon, tooltip, and menu check mark reflect (state_role)
+static STATE_ROLE: Mutex<audio::Role> = Mutex::new(audio::Role::Console);
+static SHOW_MUTE: AtomicBool = AtomicBool::new(true);
+// Mute state shown by the last icon update, so the poll timer only redraws on a change
+static SHOWN_MUTED: AtomicBool = AtomicBool::new(false);
+static SHOW_NOTIFICATIONS: AtomicBool = AtomicBool::new(true);
+static CLICK_ACTION: Mutex<config::ClickAction> = Mutex::new(config::ClickAction::Toggle);
+static DOUBLE_CLICK_ACTION: Mutex<config::ClickAction> = Mutex::new(config::ClickAction::None);
+// Set after a double-click so the button-up that ends it doesn't also count as a click
+static IGNORE_NEXT_UP: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // Per-device system icons loaded for use_system_device_icons (None = no icon, use embedded)
+    static DEVICE_ICONS: RefCell<HashMap<String, Option<HICON>>> = RefCell::new(HashMap::new());
+    // Composite icons for tray_shows_split and show_mute_state, keyed by (media icon, comms
+    // icon or 0, muted)
+    static COMPOSITE_ICONS: RefCell<HashMap<(usize, usize, bool), Option<HICON>>> = RefCell::new(HashMap::new());
+}
+// Devices listed in the most recently shown "Switch to" submenu
+static MENU_DEVICES: Mutex<Vec<AudioDevice>> = Mutex::new(Vec::new());
+// Configured device IDs, listed at the top of the context menu
+static DEVICE_RING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// speakers_icon / headphones_icon files used instead of the embedded icons
+static CUSTOM_ICONS: Mutex<(Option<PathBuf>, Option<PathBuf>)> = Mutex::new((None, None));
 
 fn store_ptr(slot: &AtomicPtr<c_void>, ptr: *mut c_void) {
     slot.store(ptr, Ordering::Release);
@@ -54,6 +131,27 @@ fn load_msg_hwnd() -> HWND {
     HWND(load_ptr(&MSG_HWND))
 }
 
+/// Hidden message window handle (null before `setup`).
+pub fn msg_hwnd() -> HWND {
+    load_msg_hwnd()
+}
+
+/// Post a custom message to the message window so the main loop handles it on the UI thread.
+pub fn post_to_main(msg: u32) {
+    let hwnd = load_msg_hwnd();
+    if hwnd.0.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = PostMessageW(Some(hwnd), msg, WPARAM(0), LPARAM(0));
+    }
+}
+
+/// Look up a device picked from the "Switch to" submenu by its WM_APP_SWITCH_TO index.
+pub fn menu_device(index: usize) -> Option<AudioDevice> {
+    MENU_DEVICES.lock().unwrap().get(index).cloned()
+}
+
 pub fn set_notify_sound(enabled: bool) {
     NOTIFY_SOUND.store(enabled, Ordering::Release);
 }
@@ -62,49 +160,196 @@ pub fn is_notify_sound() -> bool {
     NOTIFY_SOUND.load(Ordering::Acquire)
 }
 
-/// Create tray icon with state indicators and hidden message window.
-pub fn setup(is_speakers: bool) {
-    // Load icons from embedded ICO data
-    let spk = load_icon_from_ico(SPEAKERS_ICO);
-    let hp = load_icon_from_ico(HEADPHONES_ICO);
-    store_ptr(&SPEAKER_ICON, spk.0);
-    store_ptr(&HEADPHONE_ICON, hp.0);
+/// Show the active device's own Windows icon instead of the embedded speaker/headphone art.
+pub fn set_use_system_icons(enabled: bool) {
+    USE_SYSTEM_ICONS.store(enabled, Ordering::Release);
+}
+
+/// Overlay the communications device in the icon corner when it differs from the media default.
+pub fn set_show_split(enabled: bool) {
+    SHOW_SPLIT.store(enabled, Ordering::Release);
+}
+
+/// Mark the icon and tooltip while the output is muted.
+pub fn set_show_mute_state(enabled: bool) {
+    SHOW_MUTE.store(enabled, Ordering::Release);
+}
+
+/// Show a "Switched to ..." balloon after each switch.
+pub fn set_show_notifications(enabled: bool) {
+    SHOW_NOTIFICATIONS.store(enabled, Ordering::Release);
+}
+
+/// Set what a single click on the tray icon does.
+pub fn set_click_action(action: config::ClickAction) {
+    *CLICK_ACTION.lock().unwrap() = action;
+}
+
+/// Set which role's default device the tray shows as current.
+pub fn set_state_role(role: audio::Role) {
+    *STATE_ROLE.lock().unwrap() = role;
+}
+
+/// ID of the default output for the configured state role.
+fn state_device_id() -> Option<String> {
+    audio::get_default_device_id_for_role(audio::Direction::Render, *STATE_ROLE.lock().unwrap()).ok()
+}
+
+/// Set what double-clicking the tray icon does. While it's `None`, single clicks act
+/// immediately instead of waiting out the double-click time.
+pub fn set_double_click_action(action: config::ClickAction) {
+    *DOUBLE_CLICK_ACTION.lock().unwrap() = action;
+}
+
+/// Open the Sound page of Windows Settings, or the classic Sound control panel where the
+/// ms-settings: URI isn't handled (before Windows 10).
+pub fn open_sound_settings() {
+    unsafe {
+        // ShellExecuteW returns a value above 32 on success
+        let result = ShellExecuteW(None, w!("open"), w!("ms-settings:sound"), None, None, SW_SHOWNORMAL);
+        if result.0 as usize <= 32 {
+            ShellExecuteW(None, w!("open"), w!("control.exe"), w!("mmsys.cpl"), None, SW_SHOWNORMAL);
+        }
+    }
+}
+
+// Profile names (in Config::profiles order) and the active one, for the "Profile" submenu
+static PROFILES: Mutex<(Vec<String>, Option<String>)> = Mutex::new((Vec::new(), None));
+
+/// Set the profiles listed in the context menu's "Profile" submenu.
+pub fn set_profiles(names: Vec<String>, active: Option<String>) {
+    *PROFILES.lock().unwrap() = (names, active);
+}
+
+/// Set the icon files (.ico or .png) shown for the speakers and headphones instead of the
+/// embedded ones. Reloads the icons if the tray is already set up.
+pub fn set_custom_icons(speakers: Option<PathBuf>, headphones: Option<PathBuf>) {
+    *CUSTOM_ICONS.lock().unwrap() = (speakers, headphones);
+    if !load_msg_hwnd().0.is_null() {
+        load_theme_icons();
+        update_state(slot_from_wparam(LAST_SLOT.load(Ordering::Acquire)));
+    }
+}
+
+/// Set the configured devices shown at the top of the context menu.
+pub fn set_device_ring(ring: &[&str]) {
+    *DEVICE_RING.lock().unwrap() = ring.iter().map(|id| id.to_string()).collect();
+}
+
+/// Create the hidden message window and, unless `show_icon` is false (headless mode), the
+/// tray icon with state indicators. Without the icon, state updates and balloons do nothing.
+///
+/// Fails if the message window can't be created; without it the tray icon, menu, and
+/// cross-process notifications can't work.
+pub fn setup(slot: Slot, show_icon: bool) -> Result<()> {
+    load_theme_icons();
 
     // Create message window and tray icon
-    let hwnd = create_message_window();
+    let hwnd = create_message_window()?;
     store_ptr(&MSG_HWND, hwnd.0);
-    add_tray_icon(hwnd, is_speakers);
+    if show_icon {
+        add_tray_icon(hwnd, slot);
+        ICON_SHOWN.store(true, Ordering::Release);
+        unsafe { SetTimer(Some(hwnd), MUTE_TIMER_ID, MUTE_POLL_MS, None); }
+    }
+    Ok(())
 }
 
 /// Remove tray icon and clean up.
 pub fn cleanup() {
     let hwnd = load_msg_hwnd();
-    if !hwnd.0.is_null() {
+    if !hwnd.0.is_null() && ICON_SHOWN.swap(false, Ordering::AcqRel) {
         remove_tray_icon(hwnd);
     }
+    DEVICE_ICONS.with(|icons| {
+        for icon in icons.borrow_mut().drain().filter_map(|(_, icon)| icon) {
+            unsafe {
+                let _ = DestroyIcon(icon);
+            }
+        }
+    });
+    COMPOSITE_ICONS.with(|icons| {
+        for icon in icons.borrow_mut().drain().filter_map(|(_, icon)| icon) {
+            unsafe {
+                let _ = DestroyIcon(icon);
+            }
+        }
+    });
+}
+
+/// Load the speakers/headphones icons (the custom files if set, otherwise the embedded ones
+/// matching the taskbar theme), replacing any loaded before (and the composite icons drawn
+/// from them).
+fn load_theme_icons() {
+    let light = taskbar_uses_light_theme();
+    let (spk, hp) = if light {
+        (SPEAKERS_LIGHT_ICO, HEADPHONES_LIGHT_ICO)
+    } else {
+        (SPEAKERS_ICO, HEADPHONES_ICO)
+    };
+    LIGHT_THEME.store(light, Ordering::Release);
+
+    let (spk_file, hp_file) = CUSTOM_ICONS.lock().unwrap().clone();
+    for (slot, ico, file) in [(&SPEAKER_ICON, spk, spk_file), (&HEADPHONE_ICON, hp, hp_file)] {
+        let custom = file.and_then(|path| match load_icon_file(&path) {
+            Ok(icon) => Some(icon),
+            Err(e) => {
+                log::error(&format!("Failed to load icon {}: {}; using the built-in one", path.display(), e));
+                None
+            }
+        });
+        let icon = custom.map_or_else(|| load_icon_from_ico(ico), Ok).unwrap_or_else(|e| {
+            log::error(&format!("Failed to load tray icon: {}", e));
+            // The stock icon is shared, and this slot's icon is destroyed when replaced
+            unsafe { CopyIcon(fallback_icon()).unwrap_or_default() }
+        });
+        let old = slot.swap(icon.0, Ordering::AcqRel);
+        if !old.is_null() {
+            unsafe {
+                let _ = DestroyIcon(HICON(old));
+            }
+        }
+    }
+    COMPOSITE_ICONS.with(|icons| {
+        for icon in icons.borrow_mut().drain().filter_map(|(_, icon)| icon) {
+            unsafe {
+                let _ = DestroyIcon(icon);
+            }
+        }
+    });
+}
+
+/// Whether the taskbar uses the light theme. False (the default icons) if it can't be read,
+/// e.g. on Windows versions without the setting.
+fn taskbar_uses_light_theme() -> bool {
+    let mut value: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+            w!("SystemUsesLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut value as *mut u32 as *mut c_void),
+            Some(&mut size),
+        )
+        .is_ok()
+            && value != 0
+    }
 }
 
 /// Update tray icon and tooltip to reflect current device.
-pub fn update_state(is_speakers: bool) {
+pub fn update_state(slot: Slot) {
     let hwnd = load_msg_hwnd();
-    if hwnd.0.is_null() {
+    if hwnd.0.is_null() || !ICON_SHOWN.load(Ordering::Acquire) {
         return;
     }
+    LAST_SLOT.store(slot_to_wparam(slot), Ordering::Release);
 
-    let icon = if is_speakers {
-        HICON(load_ptr(&SPEAKER_ICON))
-    } else {
-        HICON(load_ptr(&HEADPHONE_ICON))
-    };
-    let tip_text = if is_speakers {
-        "Audio: Speakers"
-    } else {
-        "Audio: Headphones"
-    };
-
-    let mut tip = [0u16; 128];
-    let tip_utf16: Vec<u16> = tip_text.encode_utf16().collect();
-    tip[..tip_utf16.len()].copy_from_slice(&tip_utf16);
+    let muted = current_muted();
+    let icon = state_icon(slot, muted);
+    let tip = wide_buf(&state_tip(slot, muted));
 
     let nid = NOTIFYICONDATAW {
         cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
@@ -120,62 +365,356 @@ pub fn update_state(is_speakers: bool) {
     }
 }
 
-/// Load an HICON from embedded ICO file bytes.
-/// Picks the best size for the system tray (typically 16x16 or scaled).
-fn load_icon_from_ico(ico_data: &[u8]) -> HICON {
-    // ICO header: 2 reserved + 2 type + 2 count
-    let count = u16::from_le_bytes([ico_data[4], ico_data[5]]) as usize;
-    let target_size: u8 = 16; // System tray icon size
+/// Whether the output is muted, if show_mute_state is on (false otherwise). Also remembers
+/// the answer for the mute poll timer.
+fn current_muted() -> bool {
+    let muted = SHOW_MUTE.load(Ordering::Acquire) && audio::is_muted().unwrap_or(false);
+    SHOWN_MUTED.store(muted, Ordering::Release);
+    muted
+}
 
-    // Find the 16x16 entry (or the smallest available)
-    let mut best_offset: u32 = 0;
-    let mut best_size: u32 = 0;
-    let mut best_w: u8 = 255;
+/// Icon for the current state. With tray_shows_split on and the communications default on a
+/// different device, the comms device is drawn small in the bottom-right corner; `muted`
+/// adds a red slash.
+fn state_icon(slot: Slot, muted: bool) -> HICON {
+    let default_id = state_device_id();
+    let base = device_icon(default_id.as_deref(), slot);
 
-    for i in 0..count {
-        let entry_base = 6 + i * 16;
-        let w = ico_data[entry_base]; // 0 means 256
-        let data_size = u32::from_le_bytes([
-            ico_data[entry_base + 8],
-            ico_data[entry_base + 9],
-            ico_data[entry_base + 10],
-            ico_data[entry_base + 11],
-        ]);
-        let data_offset = u32::from_le_bytes([
-            ico_data[entry_base + 12],
-            ico_data[entry_base + 13],
-            ico_data[entry_base + 14],
-            ico_data[entry_base + 15],
-        ]);
-
-        let actual_w = if w == 0 { 255 } else { w }; // treat 256 as largest
-        // Prefer exact match, otherwise closest >= target, otherwise largest
-        if actual_w == target_size
-            || (best_w != target_size && actual_w < best_w && actual_w >= target_size)
-            || (best_w != target_size && best_w < target_size && actual_w > best_w)
-        {
-            best_w = actual_w;
-            best_offset = data_offset;
-            best_size = data_size;
+    let mut overlay = None;
+    if SHOW_SPLIT.load(Ordering::Acquire)
+        && let Ok(comms_id) = audio::get_default_device_id_for_role(audio::Direction::Render, audio::Role::Communications)
+        && default_id.as_ref() != Some(&comms_id)
+    {
+        let comms_slot = match slot {
+            Slot::Speakers => Slot::Headphones,
+            Slot::Headphones => Slot::Speakers,
+            Slot::Other => Slot::Other,
+        };
+        overlay = Some(device_icon(Some(&comms_id), comms_slot));
+    }
+    if overlay.is_none() && !muted {
+        return base;
+    }
+
+    COMPOSITE_ICONS
+        .with(|icons| {
+            *icons
+                .borrow_mut()
+                .entry((base.0 as usize, overlay.map_or(0, |icon| icon.0 as usize), muted))
+                .or_insert_with(|| composite_icon(base, overlay, muted))
+        })
+        .unwrap_or(base)
+}
+
+/// The device's system icon when use_system_device_icons is on (or it's in an extra slot) and
+/// it has one, otherwise the embedded speakers/headphones icon or a generic one for extras.
+pub fn device_icon(device_id: Option<&str>, slot: Slot) -> HICON {
+    if (USE_SYSTEM_ICONS.load(Ordering::Acquire) || slot == Slot::Other)
+        && let Some(device_id) = device_id
+        && let Some(icon) = DEVICE_ICONS.with(|icons| {
+            *icons
+                .borrow_mut()
+                .entry(device_id.to_string())
+                .or_insert_with(|| audio::get_device_icon(device_id))
+        })
+    {
+        return icon;
+    }
+
+    match slot {
+        Slot::Speakers => HICON(load_ptr(&SPEAKER_ICON)),
+        Slot::Headphones => HICON(load_ptr(&HEADPHONE_ICON)),
+        Slot::Other => fallback_icon(),
+    }
+}
+
+// szTip holds 128 UTF-16 units including the terminating NUL
+const TIP_LEN: usize = 128;
+
+/// Tooltip for the current state: the default device's friendly name, e.g. "Audio: Realtek
+/// Speakers", or the slot's generic label if the name can't be read.
+fn state_tip(slot: Slot, muted: bool) -> String {
+    let muted = if muted { " (muted)" } else { "" };
+    let name = default_device_name().unwrap_or_else(|| slot_label(slot).to_string());
+
+    // Shorten the name, not the "(muted)" suffix, if the whole thing won't fit
+    let room = TIP_LEN - 1 - "Audio: ".len() - muted.len();
+    format!("Audio: {}{}", ellipsize(&name, room), muted)
+}
+
+fn default_device_name() -> Option<String> {
+    state_device_id().and_then(|id| audio::get_device_name(&id))
+}
+
+fn slot_label(slot: Slot) -> &'static str {
+    match slot {
+        Slot::Speakers => "Speakers",
+        Slot::Headphones => "Headphones",
+        Slot::Other => "Other device",
+    }
+}
+
+/// Cut `s` to at most `max` UTF-16 units, ending in "..." if anything was dropped.
+fn ellipsize(s: &str, max: usize) -> String {
+    if s.encode_utf16().count() <= max {
+        return s.to_string();
+    }
+    let mut out = String::new();
+    let mut len = 0;
+    for c in s.chars() {
+        if len + c.len_utf16() > max - 3 {
+            break;
         }
+        len += c.len_utf16();
+        out.push(c);
+    }
+    out.push_str("...");
+    out
+}
+
+/// Encode a slot as the WM_APP_REFRESH_STATE wparam.
+pub fn slot_to_wparam(slot: Slot) -> usize {
+    match slot {
+        Slot::Headphones => 0,
+        Slot::Speakers => 1,
+        Slot::Other => 2,
+    }
+}
+
+fn slot_from_wparam(wparam: usize) -> Slot {
+    match wparam {
+        0 => Slot::Headphones,
+        1 => Slot::Speakers,
+        _ => Slot::Other,
     }
+}
 
-    let image_data = &ico_data[best_offset as usize..(best_offset + best_size) as usize];
+/// Draw `base` at tray size with `overlay` (if any) at half size over its bottom-right
+/// quarter, and a red slash across it if `muted`. The caller owns the returned icon.
+fn composite_icon(base: HICON, overlay: Option<HICON>, muted: bool) -> Option<HICON> {
+    const SIZE: i32 = 16;
+    // Opaque red, as 0xAARRGGBB
+    const SLASH_COLOR: u32 = 0xFFE0_2020;
+
+    let bmi = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: SIZE,
+            biHeight: -SIZE, // top-down
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    // All-zero AND mask: the 32-bit color bitmap's alpha channel provides transparency
+    let mask_bits = [0u8; (SIZE * SIZE / 8) as usize];
 
     unsafe {
+        let screen = GetDC(None);
+        let dc = CreateCompatibleDC(Some(screen));
+        let mut bits: *mut c_void = std::ptr::null_mut();
+        let icon = CreateDIBSection(Some(dc), &bmi, DIB_RGB_COLORS, &mut bits, None, 0)
+            .ok()
+            .and_then(|color| {
+                let mask = CreateBitmap(SIZE, SIZE, 1, 1, Some(mask_bits.as_ptr() as *const c_void));
+                let old = SelectObject(dc, color.into());
+                let drawn = DrawIconEx(dc, 0, 0, base, SIZE, SIZE, 0, None, DI_NORMAL).is_ok()
+                    && overlay.is_none_or(|overlay| {
+                        DrawIconEx(dc, SIZE / 2, SIZE / 2, overlay, SIZE / 2, SIZE / 2, 0, None, DI_NORMAL).is_ok()
+                    });
+                SelectObject(dc, old);
+
+                // GDI pens leave the alpha channel at 0 (transparent), so write the slash's
+                // pixels directly: two pixels wide, bottom-left to top-right
+                if drawn && muted && !bits.is_null() {
+                    let _ = GdiFlush();
+                    let pixels = std::slice::from_raw_parts_mut(bits as *mut u32, (SIZE * SIZE) as usize);
+                    for y in 0..SIZE {
+                        let x = SIZE - 1 - y;
+                        pixels[(y * SIZE + x) as usize] = SLASH_COLOR;
+                        if x > 0 {
+                            pixels[(y * SIZE + x - 1) as usize] = SLASH_COLOR;
+                        }
+                    }
+                }
+
+                let info = ICONINFO {
+                    fIcon: true.into(),
+                    xHotspot: 0,
+                    yHotspot: 0,
+                    hbmMask: mask,
+                    hbmColor: color,
+                };
+                let icon = if drawn { CreateIconIndirect(&info).ok() } else { None };
+                let _ = DeleteObject(color.into());
+                let _ = DeleteObject(mask.into());
+                icon
+            });
+        let _ = DeleteDC(dc);
+        ReleaseDC(None, screen);
+        icon
+    }
+}
+
+/// Show a balloon notification from the tray icon.
+pub fn show_balloon(title: &str, text: &str) {
+    let hwnd = load_msg_hwnd();
+    if hwnd.0.is_null() || !ICON_SHOWN.load(Ordering::Acquire) {
+        return;
+    }
+
+    let nid = NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: hwnd,
+        uID: TRAY_ICON_ID,
+        uFlags: NIF_INFO,
+        szInfoTitle: wide_buf(title),
+        szInfo: wide_buf(text),
+        dwInfoFlags: NIIF_INFO,
+        ..Default::default()
+    };
+    unsafe {
+        let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+    }
+}
+
+/// Show a "Switched to ..." balloon for `slot`, unless notifications are turned off.
+///
+/// The balloon is sent on its own NIM_MODIFY, so later icon/tooltip updates don't repeat it.
+pub fn notify_switch(slot: Slot) {
+    if SHOW_NOTIFICATIONS.load(Ordering::Acquire) {
+        // Extra devices are named; the main pair keeps its familiar labels
+        let label = match slot {
+            Slot::Other => default_device_name().unwrap_or_else(|| slot_label(slot).to_string()),
+            _ => slot_label(slot).to_string(),
+        };
+        show_balloon("Audio Output Switcher", &format!("Switched to {}", label));
+    }
+}
+
+/// Copy a string into a fixed-size, null-terminated UTF-16 buffer, truncating if needed.
+fn wide_buf<const N: usize>(s: &str) -> [u16; N] {
+    let mut buf = [0u16; N];
+    for (dst, src) in buf[..N - 1].iter_mut().zip(s.encode_utf16()) {
+        *dst = src;
+    }
+    buf
+}
+
+/// Load an HICON from ICO file bytes.
+/// Picks the best size for the system tray (typically 16x16 or scaled). Fails with
+/// `IconInvalid` if the header, directory, or chosen image runs past the end of the data.
+fn load_icon_from_ico(ico_data: &[u8]) -> Result<HICON> {
+    let (image, _) = find_ico_image(ico_data, 16)?; // System tray icon size
+    let icon = unsafe {
         CreateIconFromResourceEx(
-            image_data,
+            &ico_data[image],
             true, // fIcon
             0x00030000, // version (required)
             16,
             16,
             LR_DEFAULTCOLOR,
-        )
-        .expect("Failed to load icon from embedded ICO")
+        )?
+    };
+    Ok(icon)
+}
+
+/// How an ICO entry's image data is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IcoImageKind {
+    /// DIB (BITMAPINFOHEADER plus pixels and mask), the classic format.
+    Bmp,
+    /// A complete PNG file, used by Vista+ icons, mostly for the 256x256 size.
+    Png,
+}
+
+/// Read the directory of ICO file bytes and choose the image for a `target`-pixel icon.
+/// Returns where that image's data lies in `ico_data` (checked to be in bounds) and its kind.
+fn find_ico_image(ico_data: &[u8], target: u8) -> Result<(std::ops::Range<usize>, IcoImageKind)> {
+    let invalid = |msg: &str| Error::IconInvalid(msg.to_string());
+
+    // ICO header: 2 reserved + 2 type + 2 count, then a 16-byte directory entry per image
+    let header = ico_data.get(..6).ok_or_else(|| invalid("shorter than the ICO header"))?;
+    let count = u16::from_le_bytes([header[4], header[5]]) as usize;
+    if count == 0 {
+        return Err(invalid("contains no images"));
+    }
+
+    // (width, data size, data offset), split by whether the image data is PNG-compressed
+    let mut bmp_entries = Vec::with_capacity(count);
+    let mut png_entries = Vec::new();
+    for i in 0..count {
+        let entry_base = 6 + i * 16;
+        let entry = ico_data
+            .get(entry_base..entry_base + 16)
+            .ok_or_else(|| invalid("directory runs past the end of the file"))?;
+        let w = entry[0]; // 0 means 256
+        let data_size = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]);
+        let data_offset = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]);
+        let image = ico_data
+            .get(data_offset as usize..)
+            .ok_or_else(|| invalid("image offset is past the end of the file"))?;
+        if image.starts_with(PNG_SIGNATURE) {
+            png_entries.push((w, data_size, data_offset));
+        } else {
+            bmp_entries.push((w, data_size, data_offset));
+        }
+    }
+
+    // CreateIconFromResourceEx accepts PNG-compressed entries on Vista+ (the embedded icons are
+    // all PNG), but when a file mixes formats the PNG entries are typically the 256x256 ones,
+    // so prefer BMP entries and only consider PNG when there are none
+    let (entries, kind) = if bmp_entries.is_empty() {
+        (png_entries, IcoImageKind::Png)
+    } else {
+        (bmp_entries, IcoImageKind::Bmp)
+    };
+    let (_, best_size, best_offset) = entries[pick_icon_entry(&entries, target)];
+
+    let start = best_offset as usize;
+    start
+        .checked_add(best_size as usize)
+        .filter(|&end| end <= ico_data.len())
+        .map(|end| (start..end, kind))
+        .ok_or_else(|| invalid("image data runs past the end of the file"))
+}
+
+/// Load a 16x16 HICON from a .ico or .png file (told apart by content, not extension).
+fn load_icon_file(path: &Path) -> Result<HICON> {
+    let data = std::fs::read(path).map_err(|e| Error::IconInvalid(e.to_string()))?;
+    if !data.starts_with(PNG_SIGNATURE) {
+        return load_icon_from_ico(&data);
     }
+    // CreateIconFromResourceEx takes a bare PNG as icon image data and scales it
+    let icon = unsafe { CreateIconFromResourceEx(&data, true, 0x00030000, 16, 16, LR_DEFAULTCOLOR)? };
+    Ok(icon)
 }
 
-fn create_message_window() -> HWND {
+/// The stock application icon, for when an ICO can't be loaded. It's shared, so it must not
+/// be passed to DestroyIcon; copy it first to store it anywhere that is.
+fn fallback_icon() -> HICON {
+    unsafe { LoadIconW(None, IDI_APPLICATION).unwrap_or_default() }
+}
+
+/// Choose an ICO entry for a `target`-pixel icon from (width, data size, data offset) tuples.
+/// Prefers an exact match, otherwise the closest larger size, otherwise the largest smaller
+/// one. A width of 0 means 256.
+fn pick_icon_entry(entries: &[(u8, u32, u32)], target: u8) -> usize {
+    let width = |w: u8| if w == 0 { 256 } else { w as u16 };
+    let target = target as u16;
+
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| width(e.0) >= target)
+        .min_by_key(|(_, e)| width(e.0))
+        .or_else(|| entries.iter().enumerate().max_by_key(|(_, e)| width(e.0)))
+        .map_or(0, |(i, _)| i)
+}
+
+fn create_message_window() -> Result<HWND> {
     unsafe {
         let class_name = wide_str(MSG_WINDOW_CLASS);
         let wc = WNDCLASSEXW {
@@ -186,8 +725,9 @@ fn create_message_window() -> HWND {
         };
         RegisterClassExW(&wc);
 
-        let parent = HWND(-3isize as *mut c_void); // HWND_MESSAGE
-        CreateWindowExW(
+        // A hidden top-level window rather than a message-only one, so it receives broadcasts
+        // like WM_SETTINGCHANGE and the CLI can find it with FindWindowW
+        let hwnd = CreateWindowExW(
             WINDOW_EX_STYLE::default(),
             PCWSTR(class_name.as_ptr()),
             PCWSTR::null(),
@@ -196,30 +736,20 @@ fn create_message_window() -> HWND {
             0,
             0,
             0,
-            Some(parent),
             None,
             None,
             None,
-        )
-        .expect("Failed to create message window")
+            None,
+        )?;
+        Ok(hwnd)
     }
 }
 
-fn add_tray_icon(hwnd: HWND, is_speakers: bool) {
-    let icon = if is_speakers {
-        HICON(load_ptr(&SPEAKER_ICON))
-    } else {
-        HICON(load_ptr(&HEADPHONE_ICON))
-    };
-    let tip_text = if is_speakers {
-        "Audio: Speakers"
-    } else {
-        "Audio: Headphones"
-    };
-
-    let mut tip = [0u16; 128];
-    let tip_utf16: Vec<u16> = tip_text.encode_utf16().collect();
-    tip[..tip_utf16.len()].copy_from_slice(&tip_utf16);
+fn add_tray_icon(hwnd: HWND, slot: Slot) {
+    LAST_SLOT.store(slot_to_wparam(slot), Ordering::Release);
+    let muted = current_muted();
+    let icon = state_icon(slot, muted);
+    let tip = wide_buf(&state_tip(slot, muted));
 
     let nid = NOTIFYICONDATAW {
         cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
@@ -248,68 +778,53 @@ fn remove_tray_icon(hwnd: HWND) {
     }
 }
 
-fn startup_shortcut_path() -> PathBuf {
-    // %APPDATA%\Microsoft\Windows\Start Menu\Programs\Startup\AudioSwitcher.lnk
-    let appdata = dirs::config_dir().expect("Failed to find APPDATA");
-    appdata
-        .join("Microsoft")
-        .join("Windows")
-        .join("Start Menu")
-        .join("Programs")
-        .join("Startup")
-        .join("AudioSwitcher.lnk")
-}
-
-fn is_autostart_enabled() -> bool {
-    startup_shortcut_path().exists()
-}
-
-fn set_autostart(enable: bool) {
-    let shortcut_path = startup_shortcut_path();
-    if enable {
-        let exe_path = std::env::current_exe().expect("Failed to get current exe path");
-        let exe_dir = exe_path.parent().expect("Failed to get exe directory");
-
-        unsafe {
-            let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
-
-            let result: Result<(), windows::core::Error> = (|| {
-                let shell_link: IShellLinkW =
-                    CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
-
-                let exe_wide = wide_str(&exe_path.to_string_lossy());
-                shell_link.SetPath(PCWSTR(exe_wide.as_ptr()))?;
-
-                let dir_wide = wide_str(&exe_dir.to_string_lossy());
-                shell_link.SetWorkingDirectory(PCWSTR(dir_wide.as_ptr()))?;
-
-                let persist_file: IPersistFile = shell_link.cast()?;
-                let link_wide = BSTR::from(shortcut_path.to_string_lossy().as_ref());
-                persist_file.Save(&link_wide, true)?;
-
-                Ok(())
-            })();
-
-            CoUninitialize();
-
-            if let Err(e) = result {
-                eprintln!("Failed to create startup shortcut: {e}");
-            }
-        }
-    } else {
-        let _ = std::fs::remove_file(&shortcut_path);
-    }
-}
-
 fn show_context_menu(hwnd: HWND) {
     unsafe {
-        let hmenu = CreatePopupMenu().expect("Failed to create popup menu");
+        let Ok(hmenu) = CreatePopupMenu() else {
+            log::error("Failed to create the tray menu");
+            return;
+        };
 
+        let switch_text = wide_str("Switch to");
+        let settings_text = wide_str("Open Sound Settings");
         let reconfig_text = wide_str("Reconfigure");
         let sound_text = wide_str("Notification Sound");
         let autostart_text = wide_str("Start with Windows");
         let exit_text = wide_str("Exit");
 
+        // Configured devices go at the top level, followed by the full "Switch to" list.
+        // MENU_DEVICES holds both, in that order, so menu IDs index straight into it.
+        let devices = audio::list_devices_with_disabled().unwrap_or_default();
+        let current_id = state_device_id().unwrap_or_default();
+        let configured: Vec<AudioDevice> = DEVICE_RING
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|id| devices.iter().find(|d| &d.id == id).cloned())
+            .collect();
+
+        for (i, dev) in configured.iter().enumerate() {
+            let label = device_menu_label(dev);
+            let _ = AppendMenuW(
+                hmenu,
+                device_menu_flags(dev, &current_id),
+                IDM_DEVICE_BASE + i,
+                PCWSTR(label.as_ptr()),
+            );
+        }
+        if !devices.is_empty() {
+            if let Some(submenu) = build_device_submenu(&devices, &current_id, configured.len()) {
+                let _ = AppendMenuW(hmenu, MF_POPUP, submenu.0 as usize, PCWSTR(switch_text.as_ptr()));
+            }
+            let _ = AppendMenuW(hmenu, MF_SEPARATOR, 0, PCWSTR::null());
+        }
+        *MENU_DEVICES.lock().unwrap() = configured.into_iter().chain(devices).collect();
+
+        if let Some(submenu) = build_profile_submenu() {
+            let profile_text = wide_str("Profile");
+            let _ = AppendMenuW(hmenu, MF_POPUP, submenu.0 as usize, PCWSTR(profile_text.as_ptr()));
+        }
+        let _ = AppendMenuW(hmenu, MF_STRING, IDM_SOUND_SETTINGS, PCWSTR(settings_text.as_ptr()));
         let _ = AppendMenuW(hmenu, MF_STRING, IDM_RECONFIGURE, PCWSTR(reconfig_text.as_ptr()));
 
         let _ = AppendMenuW(hmenu, MF_SEPARATOR, 0, PCWSTR::null());
@@ -321,7 +836,7 @@ fn show_context_menu(hwnd: HWND) {
         };
         let _ = AppendMenuW(hmenu, sound_flags, IDM_NOTIFY_SOUND, PCWSTR(sound_text.as_ptr()));
 
-        let autostart_flags = if is_autostart_enabled() {
+        let autostart_flags = if autostart::is_enabled() {
             MF_STRING | MF_CHECKED
         } else {
             MF_STRING | MF_UNCHECKED
@@ -343,6 +858,65 @@ fn show_context_menu(hwnd: HWND) {
     }
 }
 
+/// Build the "Switch to" submenu: every active device (current default checked) plus disabled
+/// devices, which are enabled when picked. Entry `i` gets ID IDM_DEVICE_BASE + `first_index` + i.
+fn build_device_submenu(devices: &[AudioDevice], current_id: &str, first_index: usize) -> Option<HMENU> {
+    unsafe {
+        let submenu = CreatePopupMenu().ok()?;
+        for (i, dev) in devices.iter().enumerate() {
+            let label = device_menu_label(dev);
+            let _ = AppendMenuW(
+                submenu,
+                device_menu_flags(dev, current_id),
+                IDM_DEVICE_BASE + first_index + i,
+                PCWSTR(label.as_ptr()),
+            );
+        }
+        Some(submenu)
+    }
+}
+
+/// Build the "Profile" submenu with the active profile checked, or None if there are no
+/// profiles.
+fn build_profile_submenu() -> Option<HMENU> {
+    let (names, active) = PROFILES.lock().unwrap().clone();
+    if names.is_empty() {
+        return None;
+    }
+    unsafe {
+        let submenu = CreatePopupMenu().ok()?;
+        for (i, name) in names.iter().enumerate() {
+            let flags = if active.as_ref() == Some(name) { MF_STRING | MF_CHECKED } else { MF_STRING | MF_UNCHECKED };
+            let label = wide_str(name);
+            let _ = AppendMenuW(submenu, flags, IDM_PROFILE_BASE + i, PCWSTR(label.as_ptr()));
+        }
+        Some(submenu)
+    }
+}
+
+fn device_menu_label(dev: &AudioDevice) -> Vec<u16> {
+    // Owner-drawn items would be needed for a real grey-out; MF_GRAYED would also
+    // make the entry unselectable, so mark disabled devices in the label instead.
+    if dev.state == DeviceState::Disabled {
+        wide_str(&format!("{} (disabled)", dev.name))
+    } else if !dev.connected {
+        wide_str(&format!("{} (unplugged)", dev.name))
+    } else {
+        wide_str(&dev.name)
+    }
+}
+
+fn device_menu_flags(dev: &AudioDevice, current_id: &str) -> MENU_ITEM_FLAGS {
+    if dev.id == current_id {
+        MF_STRING | MF_CHECKED
+    } else if dev.state == DeviceState::Unplugged {
+        // Can't be made the default until it's plugged back in
+        MF_STRING | MF_UNCHECKED | MF_GRAYED
+    } else {
+        MF_STRING | MF_UNCHECKED
+    }
+}
+
 unsafe extern "system" fn wndproc(
     hwnd: HWND,
     msg: u32,
@@ -354,7 +928,21 @@ unsafe extern "system" fn wndproc(
             let event = lparam.0 as u32;
             match event {
                 WM_LBUTTONUP => {
-                    unsafe { let _ = PostMessageW(Some(hwnd), WM_APP_TOGGLE, WPARAM(0), LPARAM(0)); }
+                    if IGNORE_NEXT_UP.swap(false, Ordering::AcqRel) {
+                        // Second button-up of a double-click
+                    } else if *DOUBLE_CLICK_ACTION.lock().unwrap() == config::ClickAction::None {
+                        run_click_action(hwnd, *CLICK_ACTION.lock().unwrap());
+                    } else {
+                        unsafe { SetTimer(Some(hwnd), CLICK_TIMER_ID, GetDoubleClickTime(), None); }
+                    }
+                }
+                WM_LBUTTONDBLCLK => {
+                    let action = *DOUBLE_CLICK_ACTION.lock().unwrap();
+                    if action != config::ClickAction::None {
+                        unsafe { let _ = KillTimer(Some(hwnd), CLICK_TIMER_ID); }
+                        IGNORE_NEXT_UP.store(true, Ordering::Release);
+                        run_click_action(hwnd, action);
+                    }
                 }
                 WM_RBUTTONUP => {
                     show_context_menu(hwnd);
@@ -363,25 +951,76 @@ unsafe extern "system" fn wndproc(
             }
             LRESULT(0)
         }
+        WM_TIMER if wparam.0 == CLICK_TIMER_ID => {
+            // No double-click followed, so it was a single click
+            unsafe {
+                let _ = KillTimer(Some(hwnd), CLICK_TIMER_ID);
+            }
+            run_click_action(hwnd, *CLICK_ACTION.lock().unwrap());
+            LRESULT(0)
+        }
+        WM_TIMER if wparam.0 == MUTE_TIMER_ID => {
+            // Mute can be toggled from the keyboard or the volume flyout without telling us
+            if SHOW_MUTE.load(Ordering::Acquire)
+                && audio::is_muted().unwrap_or(false) != SHOWN_MUTED.load(Ordering::Acquire)
+            {
+                update_state(slot_from_wparam(LAST_SLOT.load(Ordering::Acquire)));
+            }
+            LRESULT(0)
+        }
         WM_APP_REFRESH_STATE => {
-            update_state(wparam.0 != 0);
+            let slot = slot_from_wparam(wparam.0);
+            update_state(slot);
+            // lparam is set when another process (the CLI) just switched
+            if lparam.0 != 0 {
+                notify_switch(slot);
+            }
+            LRESULT(0)
+        }
+        WM_SETTINGCHANGE => {
+            // Sent with "ImmersiveColorSet" when the theme changes, but checking is cheap
+            if taskbar_uses_light_theme() != LIGHT_THEME.load(Ordering::Acquire) {
+                load_theme_icons();
+                update_state(slot_from_wparam(LAST_SLOT.load(Ordering::Acquire)));
+            }
             LRESULT(0)
         }
+        WM_POWERBROADCAST => {
+            if wparam.0 as u32 == PBT_POWERSETTINGCHANGE
+                && let Some(on_battery) = unsafe { power::parse_setting_change(lparam) }
+            {
+                unsafe { let _ = PostMessageW(Some(hwnd), WM_APP_POWER_SOURCE, WPARAM(on_battery as usize), LPARAM(0)); }
+            }
+            LRESULT(1)
+        }
         WM_COMMAND => {
             let id = wparam.0 & 0xFFFF;
             match id {
                 IDM_RECONFIGURE => {
                     unsafe { let _ = PostMessageW(Some(hwnd), WM_APP_RECONFIGURE, WPARAM(0), LPARAM(0)); }
                 }
+                IDM_SOUND_SETTINGS => {
+                    open_sound_settings();
+                }
                 IDM_NOTIFY_SOUND => {
                     unsafe { let _ = PostMessageW(Some(hwnd), WM_APP_TOGGLE_SOUND, WPARAM(0), LPARAM(0)); }
                 }
                 IDM_AUTOSTART => {
-                    set_autostart(!is_autostart_enabled());
+                    if let Err(e) = autostart::set_enabled(!autostart::is_enabled()) {
+                        log::error(&format!("Failed to change autostart: {}", e));
+                    }
                 }
                 IDM_EXIT => {
                     unsafe { PostQuitMessage(0); }
                 }
+                id if (IDM_PROFILE_BASE..IDM_DEVICE_BASE).contains(&id) => {
+                    let index = id - IDM_PROFILE_BASE;
+                    unsafe { let _ = PostMessageW(Some(hwnd), WM_APP_PROFILE, WPARAM(index), LPARAM(0)); }
+                }
+                id if id >= IDM_DEVICE_BASE => {
+                    let index = id - IDM_DEVICE_BASE;
+                    unsafe { let _ = PostMessageW(Some(hwnd), WM_APP_SWITCH_TO, WPARAM(index), LPARAM(0)); }
+                }
                 _ => {}
             }
             LRESULT(0)
@@ -390,6 +1029,125 @@ unsafe extern "system" fn wndproc(
     }
 }
 
+fn run_click_action(hwnd: HWND, action: config::ClickAction) {
+    let msg = match action {
+        config::ClickAction::None => return,
+        config::ClickAction::SoundSettings => {
+            open_sound_settings();
+            return;
+        }
+        config::ClickAction::Toggle => WM_APP_TOGGLE,
+        config::ClickAction::Reconfigure => WM_APP_RECONFIGURE,
+    };
+    unsafe { let _ = PostMessageW(Some(hwnd), msg, WPARAM(0), LPARAM(0)); }
+}
+
 fn wide_str(s: &str) -> Vec<u16> {
     s.encode_utf16().chain(std::iter::once(0)).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ICO file bytes with one directory entry per (width, image data) pair.
+    fn ico(images: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut data = vec![0, 0, 1, 0];
+        data.extend((images.len() as u16).to_le_bytes());
+        let mut offset = 6 + 16 * images.len();
+        for &(width, image) in images {
+            data.extend([width, width, 0, 0, 1, 0, 32, 0]);
+            data.extend((image.len() as u32).to_le_bytes());
+            data.extend((offset as u32).to_le_bytes());
+            offset += image.len();
+        }
+        for &(_, image) in images {
+            data.extend(image);
+        }
+        data
+    }
+
+    fn png_image() -> Vec<u8> {
+        [PNG_SIGNATURE, b"rest of the png".as_slice()].concat()
+    }
+
+    fn bmp_image() -> Vec<u8> {
+        // BITMAPINFOHEADER starts with its own size
+        let mut image = 40u32.to_le_bytes().to_vec();
+        image.resize(40, 0);
+        image
+    }
+
+    #[test]
+    fn bmp_16_beats_png_256() {
+        let (png, bmp) = (png_image(), bmp_image());
+        let data = ico(&[(0, &png[..]), (16, &bmp[..])]);
+
+        let (image, kind) = find_ico_image(&data, 16).unwrap();
+        assert_eq!(kind, IcoImageKind::Bmp);
+        assert_eq!(image, 6 + 32 + png.len()..data.len());
+    }
+
+    #[test]
+    fn bmp_preferred_even_at_a_worse_size() {
+        let (png, bmp) = (png_image(), bmp_image());
+        let data = ico(&[(16, &png[..]), (32, &bmp[..])]);
+
+        let (image, kind) = find_ico_image(&data, 16).unwrap();
+        assert_eq!(kind, IcoImageKind::Bmp);
+        assert_eq!(&data[image], bmp.as_slice());
+    }
+
+    #[test]
+    fn png_only_ico_uses_png() {
+        let png = png_image();
+        let data = ico(&[(0, &png[..])]);
+
+        let (image, kind) = find_ico_image(&data, 16).unwrap();
+        assert_eq!(kind, IcoImageKind::Png);
+        assert_eq!(&data[image], png.as_slice());
+    }
+
+    #[test]
+    fn truncated_ico_is_invalid() {
+        let bmp = bmp_image();
+        let data = ico(&[(16, &bmp[..])]);
+
+        assert!(matches!(find_ico_image(&data[..4], 16), Err(Error::IconInvalid(_))));
+        assert!(matches!(find_ico_image(&data[..20], 16), Err(Error::IconInvalid(_))));
+        assert!(matches!(find_ico_image(&data[..data.len() - 1], 16), Err(Error::IconInvalid(_))));
+    }
+
+    /// (width, data size, data offset) entries with the given widths.
+    fn entries(widths: &[u8]) -> Vec<(u8, u32, u32)> {
+        widths.iter().map(|&w| (w, 0, 0)).collect()
+    }
+
+    #[test]
+    fn pick_only_256() {
+        assert_eq!(pick_icon_entry(&entries(&[0]), 16), 0);
+    }
+
+    #[test]
+    fn pick_exact_16() {
+        assert_eq!(pick_icon_entry(&entries(&[16, 32, 48]), 16), 0);
+        assert_eq!(pick_icon_entry(&entries(&[48, 32, 16]), 16), 2);
+    }
+
+    #[test]
+    fn pick_closest_larger() {
+        assert_eq!(pick_icon_entry(&entries(&[32, 24]), 16), 1);
+    }
+
+    #[test]
+    fn pick_largest_smaller() {
+        assert_eq!(pick_icon_entry(&entries(&[8, 12]), 16), 1);
+    }
+
+    #[test]
+    fn embedded_icons_parse() {
+        for data in [SPEAKERS_ICO, HEADPHONES_ICO, SPEAKERS_LIGHT_ICO, HEADPHONES_LIGHT_ICO] {
+            assert!(find_ico_image(data, 16).is_ok());
+        }
+    }
+}